@@ -0,0 +1,111 @@
+use std::{collections::BTreeMap, path::Path};
+
+use lazy_static::lazy_static;
+use openapiv3::{Contact, License};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use super::openapi_gen::{GeneratorOptions, OutputFormat, SharedResponse};
+
+lazy_static! {
+    static ref ENV_VAR_RE: Regex = Regex::new(r"\$\{(?P<name>\w+)\}").unwrap();
+}
+
+/// The contents of a configuration file passed via `--config`.
+///
+/// Every value is optional, flags given on the command line take precedence over the values in the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct FileConfig {
+    /// Paths to the proto files.
+    pub proto: Vec<String>,
+    /// Title of the OpenAPI document.
+    pub title: Option<String>,
+    /// Version of the OpenAPI document.
+    pub version: Option<String>,
+    /// Use `git describe --tags` in the proto directory as the version of the OpenAPI document.
+    pub version_from_git: bool,
+    /// Description of the OpenAPI document. If missing, the comment at the top of the first proto file is used.
+    pub description: Option<String>,
+    /// The contact of the API, with its `name`, `email` and `url`.
+    pub contact: Option<Contact>,
+    /// The license of the API, with its `name` and `url`.
+    pub license: Option<License>,
+    /// Path to the output OpenAPI file.
+    pub output: Option<String>,
+    /// Format of the output file, derived from the extension of the output path if missing.
+    pub format: Option<OutputFormat>,
+    /// Options for the generator, named like the fields of `GeneratorOptions` in kebab-case.
+    ///
+    /// Most of them match the long command line flags, but flags which can be given multiple times are lists named in
+    /// plural, like `servers` (`--server`), `server-variables` (`--server-var`), `descriptor-sets` (`--descriptor-set`)
+    /// and `additional-tags` (`--additional-tag`).
+    #[serde(flatten)]
+    pub generator: GeneratorOptions,
+}
+
+impl FileConfig {
+    /// Reads a configuration file. The file is expected to be YAML (which includes JSON).
+    pub fn load(path: &Path) -> Result<FileConfig, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path)?;
+        let config = serde_yaml::from_reader(file)?;
+
+        Ok(config)
+    }
+}
+
+/// Reads a responses config passed via `--responses-config`, which maps status codes to shared responses.
+pub fn load_responses(path: &Path) -> Result<BTreeMap<u16, SharedResponse>, Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(path)?;
+    let responses = serde_yaml::from_reader(file)?;
+
+    Ok(responses)
+}
+
+/// Replaces `${VAR}` in a value with the environment variable `VAR`.
+///
+/// Undefined variables are an error, unless `allow_missing` is set, in which case they are replaced with an empty string.
+pub fn expand_env(value: &str, allow_missing: bool) -> Result<String, String> {
+    let mut missing = None;
+    let expanded = ENV_VAR_RE.replace_all(value, |c: &Captures| match std::env::var(&c["name"]) {
+        Ok(var) => var,
+        Err(_) => {
+            if allow_missing {
+                eprintln!("warning: the environment variable {} used in '{}' is not set", &c["name"], value);
+            } else if missing.is_none() {
+                missing = Some(c["name"].to_string());
+            }
+            String::new()
+        }
+    });
+
+    match missing {
+        Some(name) => Err(format!("The environment variable '{}' used in '{}' is not set", name, value)),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_is_read_like_in_the_document() {
+        let config: FileConfig = serde_yaml::from_str(r#"
+            description: The users API.
+            contact:
+              name: API Team
+              url: https://example.com
+            license:
+              name: MIT
+            servers:
+              - https://api.example.com
+        "#).unwrap();
+
+        assert_eq!(config.description.as_deref(), Some("The users API."));
+        let contact = config.contact.unwrap();
+        assert_eq!((contact.name.as_deref(), contact.url.as_deref()), (Some("API Team"), Some("https://example.com")));
+        assert_eq!(config.license.unwrap().name, "MIT");
+        assert_eq!(config.generator.servers, ["https://api.example.com"]);
+    }
+}
@@ -0,0 +1,40 @@
+use thiserror::Error;
+
+/// An error which aborts the generation of a document.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// protoc couldn't be run or failed to compile the proto files.
+    #[error("failed to compile the proto files: {0}")]
+    Protoc(Box<dyn std::error::Error>),
+    /// The descriptor sets of the `descriptor_sets` option couldn't be read or merged.
+    #[error("failed to read descriptor sets: {0}")]
+    DescriptorSets(Box<dyn std::error::Error>),
+    /// The descriptor set isn't a valid `FileDescriptorSet`.
+    #[error("failed to decode the descriptor set: {0}")]
+    Decode(#[from] prost::DecodeError),
+    /// An element of the descriptor set lacks a field which protoc always sets, like the name of a method.
+    #[error("invalid descriptor: {0}")]
+    InvalidDescriptor(String),
+    /// A field has a message, enum or group type, but no type name, and the `strict` option is set.
+    #[error("field {field} has the type {field_type}, but no type name")]
+    MissingTypeName { field: String, field_type: String },
+    /// Multiple messages or enums got the same schema name.
+    #[error("multiple schemas are named {0}, use --schema-naming full to qualify them with their package")]
+    SchemaCollision(String),
+    /// Multiple methods have different callbacks of the same name.
+    #[error("multiple different callbacks are named {0}, callbacks shared by methods need to be identical")]
+    CallbackCollision(String),
+    /// A path was generated without any method mapped to it.
+    #[error("a path has no methods mapped to it")]
+    EmptyPath,
+    /// Elements of the proto files have no description, although the `require_descriptions` option requires one.
+    #[error("{} elements have no description:\n{}", .0.len(), .0.join("\n"))]
+    Undocumented(Vec<String>),
+    /// The document couldn't be serialized or written.
+    #[error("failed to write the document: {0}")]
+    Write(Box<dyn std::error::Error>),
+    /// No operations were generated, although the `allow_empty_paths` option isn't set.
+    #[error("no operations were generated, methods need a comment like `GET /users/{{userId:int}}` to become an operation \
+        (pass --allow-empty-paths to generate the document anyway)")]
+    NoOperations,
+}
@@ -0,0 +1,457 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use prost::{Enumeration, Message};
+
+// prost drops unknown fields while decoding, which includes every extension of the options.
+// To read extensions like validation constraints, the descriptor is decoded a second time using these messages,
+// which only contain the fields needed to find the options of each message and field.
+
+#[derive(Clone, PartialEq, Message)]
+struct RawFileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<RawFileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RawFileDescriptorProto {
+    #[prost(string, optional, tag = "2")]
+    package: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    message_type: Vec<RawDescriptorProto>,
+    #[prost(message, repeated, tag = "6")]
+    service: Vec<RawServiceDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RawDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    field: Vec<RawFieldDescriptorProto>,
+    #[prost(message, repeated, tag = "3")]
+    nested_type: Vec<RawDescriptorProto>,
+    /// The serialized `MessageOptions`, since the extensions to read are only known at runtime.
+    #[prost(bytes = "vec", optional, tag = "7")]
+    options: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RawFieldDescriptorProto {
+    #[prost(message, optional, tag = "8")]
+    options: Option<RawFieldOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RawFieldOptions {
+    /// The `buf.validate.field` extension.
+    #[prost(message, optional, tag = "1159")]
+    buf_validate: Option<FieldConstraints>,
+    /// The `validate.rules` extension of protoc-gen-validate, whose rules share their layout with `buf.validate`.
+    #[prost(message, optional, tag = "1071")]
+    validate_rules: Option<FieldConstraints>,
+    /// The `google.api.field_behavior` extension.
+    #[prost(enumeration = "FieldBehavior", repeated, tag = "1052")]
+    field_behavior: Vec<i32>,
+}
+
+/// How a field is used in requests and responses, a subset of `google.api.FieldBehavior`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Enumeration)]
+pub enum FieldBehavior {
+    Unspecified = 0,
+    Optional = 1,
+    Required = 2,
+    OutputOnly = 3,
+    InputOnly = 4,
+    Immutable = 5,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RawServiceDescriptorProto {
+    #[prost(message, repeated, tag = "2")]
+    method: Vec<RawMethodDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RawMethodDescriptorProto {
+    #[prost(message, optional, tag = "4")]
+    options: Option<RawMethodOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RawMethodOptions {
+    /// The `google.api.http` extension.
+    #[prost(message, optional, tag = "72295728")]
+    http: Option<HttpRule>,
+}
+
+/// The HTTP route of a method, a subset of `google.api.HttpRule`.
+///
+/// Only one of the patterns is set, in the proto definition they are part of a `oneof`.
+#[derive(Clone, PartialEq, Message)]
+pub struct HttpRule {
+    #[prost(string, optional, tag = "2")]
+    pub get: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub put: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub post: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub delete: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub patch: Option<String>,
+    #[prost(message, optional, tag = "8")]
+    pub custom: Option<CustomHttpPattern>,
+    /// The field of the request message used as the body, `*` for the whole message.
+    #[prost(string, optional, tag = "7")]
+    pub body: Option<String>,
+    #[prost(message, repeated, tag = "11")]
+    pub additional_bindings: Vec<HttpRule>,
+}
+
+/// A route with a method other than the predefined ones, like `HEAD`.
+#[derive(Clone, PartialEq, Message)]
+pub struct CustomHttpPattern {
+    #[prost(string, optional, tag = "1")]
+    pub kind: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub path: Option<String>,
+}
+
+impl HttpRule {
+    /// Returns the HTTP method (in upper case) and the path template of the rule, if it has a pattern.
+    pub fn pattern(&self) -> Option<(String, &str)> {
+        let patterns = [("GET", &self.get), ("PUT", &self.put), ("POST", &self.post), ("DELETE", &self.delete), ("PATCH", &self.patch)];
+        patterns.iter()
+            .find_map(|(method, path)| path.as_deref().map(|path| (method.to_string(), path)))
+            .or_else(|| {
+                let custom = self.custom.as_ref()?;
+                Some((custom.kind.as_deref()?.to_uppercase(), custom.path.as_deref()?))
+            })
+    }
+}
+
+/// The constraints of a field, a subset of `buf.validate.FieldConstraints` (or `validate.FieldRules` of protoc-gen-validate).
+#[derive(Clone, PartialEq, Message)]
+pub struct FieldConstraints {
+    #[prost(message, optional, tag = "1")]
+    pub float: Option<FloatRules>,
+    #[prost(message, optional, tag = "2")]
+    pub double: Option<DoubleRules>,
+    #[prost(message, optional, tag = "3")]
+    pub int32: Option<Int32Rules>,
+    #[prost(message, optional, tag = "4")]
+    pub int64: Option<Int64Rules>,
+    #[prost(message, optional, tag = "5")]
+    pub uint32: Option<UInt32Rules>,
+    #[prost(message, optional, tag = "6")]
+    pub uint64: Option<UInt64Rules>,
+    #[prost(message, optional, tag = "7")]
+    pub sint32: Option<SInt32Rules>,
+    #[prost(message, optional, tag = "8")]
+    pub sint64: Option<SInt64Rules>,
+    #[prost(message, optional, tag = "9")]
+    pub fixed32: Option<Fixed32Rules>,
+    #[prost(message, optional, tag = "10")]
+    pub fixed64: Option<Fixed64Rules>,
+    #[prost(message, optional, tag = "11")]
+    pub sfixed32: Option<SFixed32Rules>,
+    #[prost(message, optional, tag = "12")]
+    pub sfixed64: Option<SFixed64Rules>,
+    #[prost(message, optional, tag = "14")]
+    pub string: Option<StringRules>,
+    #[prost(message, optional, tag = "18")]
+    pub repeated: Option<RepeatedRules>,
+}
+
+/// The constraints of a string field.
+#[derive(Clone, PartialEq, Message)]
+pub struct StringRules {
+    #[prost(uint64, optional, tag = "19")]
+    pub len: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub min_len: Option<u64>,
+    #[prost(uint64, optional, tag = "3")]
+    pub max_len: Option<u64>,
+    #[prost(string, optional, tag = "6")]
+    pub pattern: Option<String>,
+    #[prost(bool, optional, tag = "12")]
+    pub email: Option<bool>,
+    #[prost(bool, optional, tag = "13")]
+    pub hostname: Option<bool>,
+    #[prost(bool, optional, tag = "15")]
+    pub ipv4: Option<bool>,
+    #[prost(bool, optional, tag = "16")]
+    pub ipv6: Option<bool>,
+    #[prost(bool, optional, tag = "17")]
+    pub uri: Option<bool>,
+    #[prost(bool, optional, tag = "18")]
+    pub uri_ref: Option<bool>,
+    #[prost(bool, optional, tag = "22")]
+    pub uuid: Option<bool>,
+}
+
+impl StringRules {
+    /// Returns the OpenAPI format matching the well-known rule of the string, if there is one.
+    ///
+    /// Rules without a matching format (like `ip`, which allows IPv4 and IPv6) are skipped.
+    pub fn format(&self) -> Option<&'static str> {
+        let formats = [
+            ("email", self.email),
+            ("hostname", self.hostname),
+            ("ipv4", self.ipv4),
+            ("ipv6", self.ipv6),
+            ("uri", self.uri),
+            ("uri-reference", self.uri_ref),
+            ("uuid", self.uuid),
+        ];
+        formats.iter().find(|(_, set)| set.unwrap_or(false)).map(|(format, _)| *format)
+    }
+}
+
+/// The constraints of a repeated field.
+#[derive(Clone, PartialEq, Message)]
+pub struct RepeatedRules {
+    #[prost(uint64, optional, tag = "1")]
+    pub min_items: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub max_items: Option<u64>,
+    #[prost(bool, optional, tag = "3")]
+    pub unique: Option<bool>,
+}
+
+/// The bounds of a numeric field, converted to the type used by the schema.
+///
+/// `lt` and `gt` are exclusive, `lte` and `gte` inclusive. `constant` is the only allowed value, if set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bounds<T> {
+    pub constant: Option<T>,
+    pub lt: Option<T>,
+    pub lte: Option<T>,
+    pub gt: Option<T>,
+    pub gte: Option<T>,
+}
+
+/// Defines the rules of a numeric type, which all share the same layout but differ in the type of the values.
+macro_rules! numeric_rules {
+    ($name:ident, $proto_type:ident, $ty:ty, $bound:ty) => {
+        #[derive(Clone, PartialEq, Message)]
+        pub struct $name {
+            /// The `const` rule, renamed since `const` is a keyword.
+            #[prost($proto_type, optional, tag = "1")]
+            pub constant: Option<$ty>,
+            #[prost($proto_type, optional, tag = "2")]
+            pub lt: Option<$ty>,
+            #[prost($proto_type, optional, tag = "3")]
+            pub lte: Option<$ty>,
+            #[prost($proto_type, optional, tag = "4")]
+            pub gt: Option<$ty>,
+            #[prost($proto_type, optional, tag = "5")]
+            pub gte: Option<$ty>,
+        }
+
+        impl $name {
+            /// Returns the bounds, skipping values which don't fit into the type of the schema.
+            pub fn bounds(&self) -> Bounds<$bound> {
+                let convert = |value: Option<$ty>| value.and_then(|value| <$bound>::try_from(value).ok());
+                Bounds {
+                    constant: convert(self.constant),
+                    lt: convert(self.lt),
+                    lte: convert(self.lte),
+                    gt: convert(self.gt),
+                    gte: convert(self.gte),
+                }
+            }
+        }
+    };
+}
+
+numeric_rules!(FloatRules, float, f32, f64);
+numeric_rules!(DoubleRules, double, f64, f64);
+numeric_rules!(Int32Rules, int32, i32, i64);
+numeric_rules!(Int64Rules, int64, i64, i64);
+numeric_rules!(UInt32Rules, uint32, u32, i64);
+numeric_rules!(UInt64Rules, uint64, u64, i64);
+numeric_rules!(SInt32Rules, sint32, i32, i64);
+numeric_rules!(SInt64Rules, sint64, i64, i64);
+numeric_rules!(Fixed32Rules, fixed32, u32, i64);
+numeric_rules!(Fixed64Rules, fixed64, u64, i64);
+numeric_rules!(SFixed32Rules, sfixed32, i32, i64);
+numeric_rules!(SFixed64Rules, sfixed64, i64, i64);
+
+impl FieldConstraints {
+    /// Returns the bounds of an integer field, if there are any.
+    pub fn integer_bounds(&self) -> Option<Bounds<i64>> {
+        self.int32.as_ref().map(Int32Rules::bounds)
+            .or_else(|| self.int64.as_ref().map(Int64Rules::bounds))
+            .or_else(|| self.uint32.as_ref().map(UInt32Rules::bounds))
+            .or_else(|| self.uint64.as_ref().map(UInt64Rules::bounds))
+            .or_else(|| self.sint32.as_ref().map(SInt32Rules::bounds))
+            .or_else(|| self.sint64.as_ref().map(SInt64Rules::bounds))
+            .or_else(|| self.fixed32.as_ref().map(Fixed32Rules::bounds))
+            .or_else(|| self.fixed64.as_ref().map(Fixed64Rules::bounds))
+            .or_else(|| self.sfixed32.as_ref().map(SFixed32Rules::bounds))
+            .or_else(|| self.sfixed64.as_ref().map(SFixed64Rules::bounds))
+    }
+
+    /// Returns the bounds of a floating point field, if there are any.
+    pub fn number_bounds(&self) -> Option<Bounds<f64>> {
+        self.float.as_ref().map(FloatRules::bounds)
+            .or_else(|| self.double.as_ref().map(DoubleRules::bounds))
+    }
+}
+
+/// Collects the validation constraints of the fields of every file in a serialized `FileDescriptorSet`.
+///
+/// The result contains one map per file, in the order of the set, which maps the path of a field
+/// (like `[4, 0, 2, 1]` for the second field of the first message) to its constraints.
+/// If a field has both `buf.validate` and protoc-gen-validate constraints, the former are used.
+pub fn collect_constraints(descriptor: &[u8]) -> Vec<HashMap<Vec<i32>, FieldConstraints>> {
+    collect_field_options(descriptor, "validation constraints", |options| {
+        options.buf_validate.clone().or_else(|| options.validate_rules.clone())
+    })
+}
+
+/// Collects the `google.api.field_behavior` of the fields of every file in a serialized `FileDescriptorSet`,
+/// in the same layout as `collect_constraints`. Unknown behaviors are skipped.
+pub fn collect_field_behaviors(descriptor: &[u8]) -> Vec<HashMap<Vec<i32>, Vec<FieldBehavior>>> {
+    collect_field_options(descriptor, "field behaviors", |options| {
+        let behaviors: Vec<FieldBehavior> = options.field_behavior.iter().filter_map(|&value| FieldBehavior::from_i32(value)).collect();
+        Some(behaviors).filter(|behaviors| !behaviors.is_empty())
+    })
+}
+
+/// Collects a value from the options of every field which has one, keyed by the path of the field per file.
+///
+/// `what` names the collected values in the warning printed if the descriptor can't be read.
+fn collect_field_options<T>(
+    descriptor: &[u8],
+    what: &str,
+    value: impl Fn(&RawFieldOptions) -> Option<T> + Copy,
+) -> Vec<HashMap<Vec<i32>, T>> {
+    fn collect<T>(
+        messages: &[RawDescriptorProto],
+        path: &mut Vec<i32>,
+        map: &mut HashMap<Vec<i32>, T>,
+        value: impl Fn(&RawFieldOptions) -> Option<T> + Copy,
+    ) {
+        for (idx, message) in messages.iter().enumerate() {
+            path.push(idx as i32);
+            for (field_idx, field) in message.field.iter().enumerate() {
+                if let Some(value) = field.options.as_ref().and_then(value) {
+                    let mut field_path = path.clone();
+                    field_path.extend(&[2, field_idx as i32]);
+                    map.insert(field_path, value);
+                }
+            }
+            path.push(3);
+            collect(&message.nested_type, path, map, value);
+            path.pop();
+            path.pop();
+        }
+    }
+
+    let set = match RawFileDescriptorSet::decode(descriptor) {
+        Ok(set) => set,
+        Err(err) => {
+            eprintln!("warning: failed to read the {}: {}", what, err);
+            return Vec::new();
+        }
+    };
+
+    set.file.iter().map(|file| {
+        let mut map = HashMap::new();
+        collect(&file.message_type, &mut vec![4], &mut map, value);
+        map
+    }).collect()
+}
+
+/// Collects the `google.api.http` options of the methods of every file in a serialized `FileDescriptorSet`.
+///
+/// The result contains one map per file, in the order of the set, which maps the path of a method
+/// (like `[6, 0, 2, 1]` for the second method of the first service) to its rule.
+pub fn collect_http_rules(descriptor: &[u8]) -> Vec<HashMap<Vec<i32>, HttpRule>> {
+    let set = match RawFileDescriptorSet::decode(descriptor) {
+        Ok(set) => set,
+        Err(err) => {
+            eprintln!("warning: failed to read the HTTP options: {}", err);
+            return Vec::new();
+        }
+    };
+
+    set.file.iter().map(|file| {
+        let mut map = HashMap::new();
+        for (service_idx, service) in file.service.iter().enumerate() {
+            for (method_idx, method) in service.method.iter().enumerate() {
+                if let Some(rule) = method.options.as_ref().and_then(|options| options.http.as_ref()) {
+                    map.insert(vec![6, service_idx as i32, 2, method_idx as i32], rule.clone());
+                }
+            }
+        }
+        map
+    }).collect()
+}
+
+/// Returns the value of the string field with the given number in a serialized message, if present.
+///
+/// If the field occurs multiple times, the last occurrence wins like for any other field.
+fn find_string_field(mut buf: &[u8], number: u64) -> Option<String> {
+    let mut value = None;
+    while !buf.is_empty() {
+        let key = prost::encoding::decode_varint(&mut buf).ok()?;
+        let length = match key & 0x7 {
+            0 => {
+                prost::encoding::decode_varint(&mut buf).ok()?;
+                0
+            }
+            1 => 8,
+            2 => prost::encoding::decode_varint(&mut buf).ok()? as usize,
+            5 => 4,
+            // groups are not supported
+            _ => return value,
+        };
+        if length > buf.len() {
+            return value;
+        }
+        if key >> 3 == number && key & 0x7 == 2 {
+            value = Some(String::from_utf8_lossy(&buf[..length]).into_owned());
+        }
+        buf = &buf[length..];
+    }
+
+    value
+}
+
+/// Collects the values of a string extension of `MessageOptions` with the given field number, for every message
+/// in a serialized `FileDescriptorSet`.
+///
+/// The result is keyed by the fully qualified name of the message, like `.helloworld.HelloMessage`.
+pub fn collect_message_options(descriptor: &[u8], number: u64) -> HashMap<String, String> {
+    fn collect(messages: &[RawDescriptorProto], prefix: &str, number: u64, map: &mut HashMap<String, String>) {
+        for message in messages {
+            let name = format!("{}.{}", prefix, message.name.as_deref().unwrap_or_default());
+            if let Some(value) = message.options.as_deref().and_then(|options| find_string_field(options, number)) {
+                map.insert(name.clone(), value);
+            }
+            collect(&message.nested_type, &name, number, map);
+        }
+    }
+
+    let set = match RawFileDescriptorSet::decode(descriptor) {
+        Ok(set) => set,
+        Err(err) => {
+            eprintln!("warning: failed to read the message options: {}", err);
+            return HashMap::new();
+        }
+    };
+
+    let mut map = HashMap::new();
+    for file in &set.file {
+        let prefix = match file.package.as_deref() {
+            Some(package) if !package.is_empty() => format!(".{}", package),
+            _ => String::new(),
+        };
+        collect(&file.message_type, &prefix, number, &mut map);
+    }
+
+    map
+}
@@ -0,0 +1,82 @@
+pub mod openapi_gen;
+pub mod config;
+mod error;
+mod extensions;
+mod prost_light;
+
+use std::path::PathBuf;
+
+use openapiv3::{Contact, License, OpenAPI};
+
+use openapi_gen::{GeneratorOptions, OpenAPIGenerator};
+
+pub use error::Error;
+
+/// Everything needed to generate an OpenAPI document, like the command line of the tool.
+#[derive(Debug, Default)]
+pub struct GenerationOptions {
+    /// Paths to the proto files. Can be empty if the generator options contain descriptor sets.
+    pub protos: Vec<PathBuf>,
+    /// The directories in which protoc looks for imports.
+    pub includes: Vec<PathBuf>,
+    /// Title of the OpenAPI document.
+    pub title: String,
+    /// Version of the OpenAPI document.
+    pub version: String,
+    /// Description of the OpenAPI document. If missing, the comment at the top of the first proto file is used.
+    pub description: Option<String>,
+    /// The contact of the API.
+    pub contact: Option<Contact>,
+    /// The license of the API.
+    pub license: Option<License>,
+    /// Options for the generator.
+    pub generator: GeneratorOptions,
+}
+
+/// Generates an OpenAPI document from proto files.
+///
+/// Fails if elements lack a required description, or if no operation was generated and empty paths aren't allowed.
+pub fn proto_to_openapi(options: GenerationOptions) -> Result<OpenAPI, Error> {
+    let mut config = prost_build::Config::new();
+    let generator = &options.generator;
+
+    if !generator.require_descriptions.is_empty() {
+        let undocumented = OpenAPIGenerator::undocumented(&mut config, &options.protos, &options.includes, generator)?;
+        if !undocumented.is_empty() {
+            return Err(Error::Undocumented(undocumented));
+        }
+    }
+
+    let mut openapi = OpenAPIGenerator::generate(&mut config, &options.protos, &options.includes, generator)?;
+
+    if !generator.allow_empty_paths && openapi_gen::count_operations(&openapi) == 0 {
+        return Err(Error::NoOperations);
+    }
+
+    openapi.info.title = options.title;
+    openapi.info.version = options.version;
+    if options.description.is_some() {
+        openapi.info.description = options.description;
+    }
+    openapi.info.contact = options.contact;
+    openapi.info.license = options.license;
+
+    Ok(openapi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_without_operations_fail() {
+        let dir = tempfile::tempdir().unwrap();
+        let proto = dir.path().join("test.proto");
+        std::fs::write(&proto, "syntax = \"proto3\"; package test; message User { string name = 1; }").unwrap();
+        let options = GenerationOptions { protos: vec![proto], includes: vec![dir.path().to_path_buf()], ..GenerationOptions::default() };
+        let err = proto_to_openapi(options).unwrap_err();
+
+        assert!(matches!(err, Error::NoOperations), "unexpected error {}", err);
+        assert!(err.to_string().starts_with("no operations were generated, methods need a comment like `GET /users/{userId:int}`"));
+    }
+}
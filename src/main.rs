@@ -1,34 +1,342 @@
-mod prost_light;
-mod openapi_gen;
-
-use std::path::Path;
+use std::{io::Write, path::Path, process::Command};
 
 use clap::load_yaml;
-use openapi_gen::OpenAPIGenerator;
+use proto2openapi::{config::{self, FileConfig}, openapi_gen::{self, DescriptionKind, OpenAPIGenerator, OutputFormat, RefStyle, SchemaNaming}, GenerationOptions};
+
+/// Exits with a clap error if a required value was neither given on the command line nor in the config file.
+fn require<T>(value: Option<T>, name: &str) -> T {
+    match value {
+        Some(value) => value,
+        None => clap::Error::with_description(
+            &format!("The argument '{}' was not provided on the command line or in the config file", name),
+            clap::ErrorKind::MissingRequiredArgument,
+        ).exit(),
+    }
+}
+
+/// Returns the output of `git describe --tags` in the given directory, if git is available and a tag exists.
+fn git_describe(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("describe")
+        .arg("--tags")
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Some(version).filter(|v| !v.is_empty())
+}
 
 /// Main function of the tool
 fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = clap::App::from_yaml(yaml).get_matches();
 
-    let protos = matches.values_of("proto").unwrap();
-    let protos: Vec<&Path> = protos.map(|p| Path::new(p)).collect();
+    let file_config = match matches.value_of("config") {
+        Some(path) => match FileConfig::load(Path::new(path)) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("error: failed to read the config file {}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => FileConfig::default(),
+    };
+
+    // flags given on the command line override the values of the config file
+    let protos: Vec<String> = match matches.values_of("proto") {
+        Some(protos) => protos.map(str::to_owned).collect(),
+        None => file_config.proto,
+    };
+    let mut options = file_config.generator;
+    if let Some(paths) = matches.values_of("descriptor-set") {
+        options.descriptor_sets = paths.map(Into::into).collect();
+    }
+    // descriptor sets replace running protoc, so no proto files are needed
+    let protos = if options.descriptor_sets.is_empty() {
+        require(Some(protos).filter(|p| !p.is_empty()), "--proto <proto>...")
+    } else {
+        protos
+    };
+    let protos: Vec<&Path> = protos.iter().map(Path::new).collect();
     let proto_dirs = protos.iter().map(|p| p.parent().unwrap()).collect::<Vec<_>>();
-    let openapi_path = Path::new(matches.value_of("OUTPUT").unwrap());
-    let openapi_title = matches.value_of("openapi-title").unwrap();
-    let openapi_version = matches.value_of("openapi-version").unwrap();
+
+    if matches.is_present("json-names") {
+        options.json_names = true;
+    }
+    if let Some(prefix) = matches.value_of("prefix-schemas") {
+        options.prefix_schemas = Some(prefix.to_string());
+    }
+    if matches.is_present("allow-get-body") {
+        options.allow_get_body = true;
+    }
+    if matches.is_present("query-params") {
+        options.query_params = true;
+    }
+    if let Some(tags) = matches.values_of("additional-tag") {
+        options.additional_tags.extend(tags.map(str::to_owned));
+    }
+    if let Some(style) = matches.value_of("oneof-style") {
+        options.oneof_style = style.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit());
+    }
+    if let Some(style) = matches.value_of("ref-style") {
+        options.ref_style = style.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit());
+    }
+    if let Some(base) = matches.value_of("ref-base") {
+        options.ref_base = Some(base.to_string());
+    }
+    if options.ref_style == RefStyle::External && options.ref_base.is_none() {
+        require(None, "--ref-base <ref-base>")
+    }
+    if let Some(number) = matches.value_of("schema-name-option") {
+        options.schema_name_option = Some(number.parse().unwrap_or_else(|_| {
+            clap::Error::value_validation_auto(format!("The field number '{}' is not a number", number)).exit()
+        }));
+    }
+    if let Some(number) = matches.value_of("example-option") {
+        options.example_option = Some(number.parse().unwrap_or_else(|_| {
+            clap::Error::value_validation_auto(format!("The field number '{}' is not a number", number)).exit()
+        }));
+    }
+    if let Some(naming) = matches.value_of("schema-naming") {
+        options.schema_naming = naming.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit());
+    }
+    if matches.is_present("prefix-package") {
+        options.schema_naming = SchemaNaming::Package;
+    }
+    if let Some(prefix) = matches.value_of("trim-leading-package") {
+        options.trim_leading_package = Some(prefix.to_string());
+    }
+    if let Some(sort) = matches.value_of("schema-sort") {
+        options.schema_sort = sort.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit());
+    }
+    if let Some(style) = matches.value_of("operation-id-style") {
+        options.operation_id_style = style.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit());
+    }
+    if let Some(case) = matches.value_of("operation-id-case") {
+        options.operation_id_case = Some(case.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit()));
+    }
+    if matches.is_present("group-schemas-by-package") {
+        options.group_schemas_by_package = true;
+    }
+    if let Some(path) = matches.value_of("dump-descriptor") {
+        options.dump_descriptor = Some(path.into());
+    }
+    if let Some(path) = matches.value_of("descriptor-cache") {
+        options.descriptor_cache = Some(path.into());
+    }
+    if let Some(path) = matches.value_of("emit-mapping") {
+        options.emit_mapping = Some(path.into());
+    }
+    if let Some(prefix) = matches.value_of("strip-path-prefix") {
+        options.strip_path_prefix = Some(prefix.to_string());
+    }
+    if let Some(prefix) = matches.value_of("path-prefix") {
+        options.path_prefix = Some(prefix.to_string());
+    }
+    if matches.is_present("version-paths") {
+        options.version_paths = true;
+    }
+    if matches.is_present("omit-unused-schemas") {
+        options.omit_unused_schemas = true;
+    }
+    if matches.is_present("no-import-schemas") {
+        options.no_import_schemas = true;
+    }
+    if matches.is_present("enum-as-string") {
+        options.enum_as_string = true;
+    }
+    if matches.is_present("enum-as-string-or-int") {
+        options.enum_as_string_or_int = true;
+    }
+    if matches.is_present("collapse-single-value-enums") {
+        options.collapse_single_value_enums = true;
+    }
+    if matches.is_present("tag-by-path") {
+        options.tag_by_path = true;
+    }
+    if matches.is_present("tag-by-file") {
+        options.tag_by_file = true;
+    }
+    if matches.is_present("title-case-tags") {
+        options.title_case_tags = true;
+    }
+    if matches.is_present("emit-index") {
+        options.emit_index = true;
+    }
+    if matches.is_present("no-additional-properties") {
+        options.no_additional_properties = true;
+    }
+    if let Some(mappings) = matches.values_of("status-from-response-name") {
+        for mapping in mappings {
+            let (suffix, code) = mapping.split_once('=')
+                .and_then(|(suffix, code)| Some((suffix, code.parse::<u16>().ok().filter(|c| (100..=599).contains(c))?)))
+                .unwrap_or_else(|| clap::Error::value_validation_auto(
+                    format!("The status mapping '{}' is not of the form Suffix=code", mapping)
+                ).exit());
+            options.status_from_response_name.insert(suffix.to_string(), code);
+        }
+    }
+    if let Some(replace) = matches.value_of("replace-default-status") {
+        options.replace_default_status = Some(replace == "true");
+    }
+    if matches.is_present("skip-license-comment") {
+        options.skip_license_comment = true;
+    }
+    if matches.is_present("path-description-from-service") {
+        options.path_description_from_service = true;
+    }
+    if matches.is_present("allow-empty-paths") {
+        options.allow_empty_paths = true;
+    }
+    if matches.is_present("require-descriptions") {
+        let kinds: Vec<DescriptionKind> = matches.values_of("require-descriptions").into_iter().flatten()
+            .map(|kind| kind.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit()))
+            .collect();
+        // without a list, every kind of element needs a description
+        options.require_descriptions = if kinds.is_empty() { DescriptionKind::ALL.to_vec() } else { kinds };
+    }
+    if matches.is_present("all-optional") {
+        options.all_optional = true;
+    }
+    if matches.is_present("optional-repeated") {
+        options.optional_repeated = true;
+    }
+    if matches.is_present("grpc-error-model") {
+        options.grpc_error_model = true;
+    }
+    if matches.is_present("bool-as-string") {
+        options.bool_as_string = true;
+    }
+    if matches.is_present("int64-as-string") {
+        options.int64_as_string = true;
+    }
+    if matches.is_present("strict") {
+        options.strict = true;
+    }
+    if let Some(depth) = matches.value_of("flatten-depth") {
+        options.flatten_depth = Some(depth.parse().unwrap_or_else(|_| {
+            clap::Error::value_validation_auto(format!("The flatten depth '{}' is not a number", depth)).exit()
+        }));
+    }
+    if let Some(path) = matches.value_of("responses-config") {
+        match config::load_responses(Path::new(path)) {
+            Ok(responses) => options.responses.extend(responses),
+            Err(err) => {
+                eprintln!("error: failed to read the responses config {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(servers) = matches.values_of("server") {
+        options.servers.extend(servers.map(str::to_owned));
+    }
+    if let Some(variables) = matches.values_of("server-var") {
+        for variable in variables {
+            let (name, default) = variable.split_once('=').unwrap_or_else(|| clap::Error::value_validation_auto(
+                format!("The server variable '{}' is not of the form name=value", variable)
+            ).exit());
+            options.server_variables.insert(name.to_string(), default.to_string());
+        }
+    }
+    if matches.is_present("allow-missing-env") {
+        options.allow_missing_env = true;
+    }
+    if let Some(depth) = matches.value_of("query-depth") {
+        options.query_depth = Some(depth.parse().unwrap_or_else(|_| {
+            clap::Error::value_validation_auto(format!("The query depth '{}' is not a number", depth)).exit()
+        }));
+    }
+    let allow_missing_env = options.allow_missing_env;
+    for server in &mut options.servers {
+        *server = config::expand_env(server, allow_missing_env).unwrap_or_else(|err| clap::Error::value_validation_auto(err).exit());
+    }
 
     let mut config = prost_build::Config::new();
-    let mut openapi = OpenAPIGenerator::generate(&mut config, &protos, &proto_dirs);
+    if matches.is_present("list") {
+        if let Err(err) = OpenAPIGenerator::list(&mut config, &protos, &proto_dirs, &options) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    openapi.info.title = openapi_title.to_string();
-    openapi.info.version = openapi_version.to_string();
+    let openapi_path = require(matches.value_of("OUTPUT").map(str::to_owned).or(file_config.output), "<OUTPUT>");
+    let openapi_path = Path::new(&openapi_path);
+    // without an explicit format, `.json` files are written as JSON and everything else as YAML
+    let format = match matches.value_of("format") {
+        Some(format) => format.parse().unwrap_or_else(|err: String| clap::Error::value_validation_auto(err).exit()),
+        None => file_config.format.unwrap_or_else(|| OutputFormat::from_path(openapi_path)),
+    };
+    let openapi_title = require(matches.value_of("openapi-title").map(str::to_owned).or(file_config.title), "--title <openapi-title>");
+    let openapi_version = match matches.value_of("openapi-version") {
+        Some(version) => version.to_string(),
+        None if matches.is_present("version-from-git") || file_config.version_from_git => {
+            let dir = proto_dirs.first().filter(|d| !d.as_os_str().is_empty()).copied().unwrap_or_else(|| Path::new("."));
+            match git_describe(dir) {
+                Some(version) => version,
+                None => {
+                    let fallback = file_config.version.unwrap_or_else(|| "0.0.0".to_string());
+                    eprintln!("warning: could not determine the version using git describe, falling back to {}", fallback);
+                    fallback
+                }
+            }
+        }
+        None => require(file_config.version, "--version <openapi-version>"),
+    };
+
+    // empty values are treated like missing ones, so a flag can be left empty in scripts
+    let info_value = |name| matches.value_of(name).filter(|value| !value.is_empty()).map(str::to_owned);
+    // each flag replaces its value in the config file, the rest of the contact and license is kept
+    let file_contact = file_config.contact.unwrap_or_default();
+    let contact = Some(openapiv3::Contact {
+        name: info_value("openapi-contact-name").or(file_contact.name),
+        email: info_value("openapi-contact-email").or(file_contact.email),
+        ..file_contact
+    }).filter(|contact| contact.name.is_some() || contact.email.is_some() || contact.url.is_some());
+    let (file_license_name, file_license_url) = file_config.license.map_or((None, None), |license| (Some(license.name), license.url));
+    let license_name = info_value("openapi-license-name").or(file_license_name).filter(|name| !name.is_empty());
+    let license = match (license_name, info_value("openapi-license-url").or(file_license_url)) {
+        (Some(name), url) => Some(openapiv3::License { name, url, ..openapiv3::License::default() }),
+        (None, Some(url)) => {
+            eprintln!("warning: ignoring the license URL {}, because a license needs a name", url);
+            None
+        }
+        (None, None) => None,
+    };
+
+    let generation = GenerationOptions {
+        protos: protos.iter().map(|proto| proto.to_path_buf()).collect(),
+        includes: proto_dirs.iter().map(|dir| dir.to_path_buf()).collect(),
+        title: openapi_title,
+        version: openapi_version,
+        description: info_value("openapi-description").or(file_config.description),
+        contact,
+        license,
+        generator: options,
+    };
+    let openapi = proto2openapi::proto_to_openapi(generation).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    });
 
-    let file = match std::fs::File::create(openapi_path) {
-        Ok(file) => file,
-        Err(err) => {
-            panic!("Failed to create file: {}", err);
+    // `-` writes to stdout for pipelines, which is why the progress is printed to stderr
+    let writer: Box<dyn Write> = if openapi_path == Path::new("-") {
+        Box::new(std::io::stdout())
+    } else {
+        match std::fs::File::create(openapi_path) {
+            Ok(file) => Box::new(file),
+            Err(err) => {
+                eprintln!("error: failed to create {}: {}", openapi_path.display(), err);
+                std::process::exit(1);
+            }
         }
     };
-    serde_yaml::to_writer(file, &openapi).unwrap();
+    if let Err(err) = openapi_gen::write_openapi(&openapi, format, writer) {
+        eprintln!("error: failed to write {}: {}", openapi_path.display(), err);
+        std::process::exit(1);
+    }
 }
@@ -1,15 +1,17 @@
-use std::{collections::HashMap, convert::TryFrom, path::Path};
+use std::{borrow::BorrowMut, collections::{BTreeMap, HashMap, HashSet, VecDeque}, convert::TryFrom, io::Write, path::{Path, PathBuf}, str::FromStr};
 
 use indexmap::IndexMap;
 use itertools::{Either, Itertools};
 use lazy_static::lazy_static;
 use multimap::MultiMap;
-use openapiv3::{ArrayType, Components, IntegerType, MediaType, NumberType, ObjectType, OpenAPI, Operation, Parameter, ParameterData, ParameterSchemaOrContent, PathStyle, ReferenceOr, RequestBody, Response, Responses, Schema, SchemaData, SchemaKind, StatusCode, StringType, Type};
+use openapiv3::{AdditionalProperties, ArrayType, Callback, Components, Discriminator, Example, IntegerFormat, IntegerType, MediaType, NumberFormat, NumberType, ObjectType, OpenAPI, Operation, Parameter, ParameterData, ParameterSchemaOrContent, PathItem, PathStyle, QueryStyle, ReferenceOr, RequestBody, Response, Responses, Schema, SchemaData, SchemaKind, Server, ServerVariable, StatusCode, StringFormat, StringType, Tag, Type, VariantOrUnknownOrEmpty};
+use prost::Message;
 use prost_build::{Comments, Config, Method, Service};
-use prost_types::{DescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto, OneofDescriptorProto, ServiceDescriptorProto, SourceCodeInfo, field_descriptor_proto::{self, Label}, source_code_info::Location};
+use prost_types::{DescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, OneofDescriptorProto, ServiceDescriptorProto, SourceCodeInfo, field_descriptor_proto::{self, Label}, source_code_info::Location};
 use regex::Regex;
+use serde::Deserialize;
 
-use super::prost_light::GetProtoFileDescriptor;
+use super::{error::Error, prost_light::{self, GetProtoFileDescriptor}, extensions::{self, FieldBehavior, FieldConstraints, HttpRule}};
 
 /// Allows to convert a location to a `Comments` object.
 pub trait Commentable {
@@ -47,11 +49,89 @@ impl Commentable for Comments {
 
 // The heart of the path generation.
 lazy_static! {
-    static ref METHOD_RE: Regex = Regex::new(r"^\s*(GET|PUT|POST|DELETE)").unwrap();
+    static ref METHOD_RE: Regex = Regex::new(r"^\s*(GET|PUT|POST|DELETE|PATCH)").unwrap();
     static ref PATH_RE: Regex = Regex::new(r"(?:/(?:(?:\w+)|(?:\{\w+:\w+\})))+").unwrap();
     static ref PARAM_RE: Regex = Regex::new(r"\{(?P<param>\w+):(?P<param_type>\w+)\}").unwrap();
     static ref BODY_RE: Regex = Regex::new(r"(\+|-) BODY").unwrap();
     static ref TAG_RE: Regex = Regex::new(r"\[([a-zA-Z0-9, ]+)\]").unwrap();
+    static ref STATUS_RE: Regex = Regex::new(r"STATUS\s+(\d+)").unwrap();
+    static ref SERVER_VAR_RE: Regex = Regex::new(r"\{(\w+)\}").unwrap();
+    static ref QUERY_RE: Regex = Regex::new(r"QUERY\s+([\w.]+(?:\s*,\s*[\w.]+)*)").unwrap();
+    static ref ERRORS_RE: Regex = Regex::new(r"ERRORS\s+(\d+=[\w.]+(?:\s*,\s*\d+=[\w.]+)*)").unwrap();
+    static ref ANNOTATION_RE: Regex = Regex::new(r"^\s*@(?P<name>[\w-]+)\s*(?P<value>.*)$").unwrap();
+    static ref VERSION_RE: Regex = Regex::new(r"^v\d+((alpha|beta)\d*)?$").unwrap();
+    static ref LANG_TYPE_RE: Regex = Regex::new(r"^\w+-type$").unwrap();
+    static ref PARAM_JSON_RE: Regex = Regex::new(r"^(?P<param>[\w.]+):(?P<message>[\w.]+)$").unwrap();
+    static ref HTTP_PARAM_RE: Regex = Regex::new(r"\{(?P<param>[\w.]+)(?:=[^}]*)?\}").unwrap();
+    static ref PREFIX_PARAM_RE: Regex = Regex::new(r"\{(?P<param>\w+)(?::(?P<param_type>\w+))?\}").unwrap();
+}
+
+lazy_static! {
+    /// Used for elements without source info, it doesn't contain any comments.
+    static ref EMPTY_LOCATION: Location = Location::default();
+}
+
+/// Parses annotations like `@param-json filter:Filter` from comment lines.
+///
+/// The result maps the annotation name (without `@`) to the trimmed values, an annotation may appear multiple times.
+pub fn parse_annotations(lines: &[String]) -> MultiMap<String, String> {
+    lines.iter()
+        .filter_map(|line| ANNOTATION_RE.captures(line))
+        .map(|c| (c["name"].to_string(), c["value"].trim().to_string()))
+        .collect()
+}
+
+/// Returns the description contained in comment lines, which are all lines except annotations.
+pub fn comment_description(lines: &[String]) -> Option<String> {
+    let description = lines.iter()
+        .filter(|line| !ANNOTATION_RE.is_match(line))
+        .map(|line| line.trim())
+        .join("\n");
+
+    Some(description.trim().to_string()).filter(|d| !d.is_empty())
+}
+
+/// Splits the comment of a method into a summary (its first line) and a description (the rest).
+///
+/// Annotations and the lines of routes (containing a method, `+ BODY`/`- BODY` or `[tags]`) are left out.
+pub fn method_summary(lines: &[String]) -> (Option<String>, Option<String>) {
+    let mut prose = lines.iter()
+        .filter(|line| ![&*ANNOTATION_RE, &*METHOD_RE, &*BODY_RE, &*TAG_RE].iter().any(|re| re.is_match(line)))
+        .map(|line| line.trim())
+        .skip_while(|line| line.is_empty());
+    let summary = prose.next().map(str::to_owned);
+    let description = prose.join("\n");
+
+    (summary, Some(description.trim().to_string()).filter(|d| !d.is_empty()))
+}
+
+/// Contains information about a proto method, which is shared by all of its paths.
+#[derive(Clone)]
+pub struct OpenAPIMethodInfo {
+    /// The fully qualified name of the service containing the method, like `helloworld.Greeter`.
+    pub service_name: String,
+    /// The fully qualified name of the method, like `helloworld.Greeter.SayHello`.
+    pub method_name: String,
+    /// The fully qualified input type.
+    pub input_type: String,
+    /// The fully qualified output type.
+    pub output_type: String,
+    /// The annotations found in the comments of the method.
+    pub annotations: MultiMap<String, String>,
+    /// The name of the proto file containing the method, without directory and extension.
+    pub file_stem: String,
+    /// The comment of the service containing the method, without annotations.
+    pub service_description: Option<String>,
+    /// Whether the service containing the method is annotated with `@deprecated` or has the `deprecated` option.
+    pub service_deprecated: bool,
+    /// Whether the method has the `deprecated` option.
+    pub deprecated: bool,
+    /// The first line of the comment of the method, without routes and annotations.
+    pub summary: Option<String>,
+    /// The comment of the method after the summary, without routes and annotations.
+    pub description: Option<String>,
+    /// The names of the callbacks of the method, which are generated into the components.
+    pub callbacks: Vec<String>,
 }
 
 /// Contains path information for a given proto method.
@@ -60,12 +140,737 @@ pub struct OpenAPIPathInfo {
     pub path: String,
     /// The query method.
     pub method: String,
-    /// The query parameters. Empty if no parameters are present.
-    pub parameters: HashMap<String, String>,
+    /// The path parameters with their types, in the order of the path. Empty if no parameters are present.
+    pub parameters: IndexMap<String, String>,
     /// `true` if the method should include a body. Defaults to `true`.
     pub include_body: bool,
+    /// `true` if the comment explicitly contains `+ BODY` or `- BODY`.
+    pub explicit_body: bool,
+    /// The field of the input message used as the body instead of the whole message, from a `google.api.http` option.
+    pub body_field: Option<String>,
     /// The path tags.
     pub tags: Vec<String>,
+    /// The additional responses of `ERRORS 404=NotFoundError, ...`, as status code and message.
+    pub errors: Vec<(u16, String)>,
+    /// The status code of the successful response from `STATUS 201`. If missing, the status of the method is used.
+    pub success_status: Option<u16>,
+    /// The fields of the input message which become query parameters, from `QUERY field1,field2`.
+    pub query: Vec<String>,
+}
+
+/// Returns an integer type with the given format.
+fn integer_type(format: IntegerFormat) -> Type {
+    Type::Integer(IntegerType { format: VariantOrUnknownOrEmpty::Item(format), ..IntegerType::default() })
+}
+
+/// Returns the type of a 64-bit integer, which is a string with the `int64_as_string` option like in the protobuf JSON mapping.
+fn integer64_type(options: &GeneratorOptions) -> Type {
+    if options.int64_as_string {
+        Type::String(StringType { format: VariantOrUnknownOrEmpty::Unknown("int64".to_string()), ..StringType::default() })
+    } else {
+        integer_type(IntegerFormat::Int64)
+    }
+}
+
+/// Maps the type of a scalar proto field to an OpenAPI type.
+///
+/// Message types without a type name fall back to an object with a warning.
+/// With the `strict` option, they fail the generation instead.
+pub fn scalar_type_to_schema(field: &FieldDescriptorProto, options: &GeneratorOptions) -> Result<Type, Error> {
+    let scalar_type = match field.r#type() {
+        // some gateways only pass booleans as the strings `true` and `false`
+        field_descriptor_proto::Type::Bool if options.bool_as_string => Type::String(StringType {
+            enumeration: vec!["true".to_string(), "false".to_string()],
+            ..StringType::default()
+        }),
+        field_descriptor_proto::Type::Bool => Type::Boolean {},
+        field_descriptor_proto::Type::String => Type::String(StringType::default()),
+        field_descriptor_proto::Type::Double => {
+            Type::Number(NumberType { format: VariantOrUnknownOrEmpty::Item(NumberFormat::Double), ..NumberType::default() })
+        }
+        field_descriptor_proto::Type::Float => {
+            Type::Number(NumberType { format: VariantOrUnknownOrEmpty::Item(NumberFormat::Float), ..NumberType::default() })
+        }
+        field_descriptor_proto::Type::Int32 | field_descriptor_proto::Type::Sint32 | field_descriptor_proto::Type::Sfixed32 => {
+            integer_type(IntegerFormat::Int32)
+        }
+        // not every unsigned 32-bit integer fits into an int32
+        field_descriptor_proto::Type::Uint32 | field_descriptor_proto::Type::Fixed32 => integer_type(IntegerFormat::Int64),
+        field_descriptor_proto::Type::Int64
+        | field_descriptor_proto::Type::Uint64
+        | field_descriptor_proto::Type::Sint64
+        | field_descriptor_proto::Type::Fixed64
+        | field_descriptor_proto::Type::Sfixed64 => integer64_type(options),
+        // bytes are base64 encoded in JSON
+        field_descriptor_proto::Type::Bytes => {
+            Type::String(StringType { format: VariantOrUnknownOrEmpty::Item(StringFormat::Byte), ..StringType::default() })
+        }
+        // message and enum fields are references, unless the descriptor is malformed
+        missing @ (field_descriptor_proto::Type::Message | field_descriptor_proto::Type::Enum | field_descriptor_proto::Type::Group) => {
+            if options.strict {
+                return Err(Error::MissingTypeName { field: field.name().to_string(), field_type: format!("{:?}", missing) });
+            }
+            eprintln!("warning: field {} has the type {:?}, but no type name, falling back to object", field.name(), missing);
+            Type::Object(ObjectType::default())
+        }
+    };
+
+    Ok(scalar_type)
+}
+
+/// Generates a server from `URL[,description]`, with the `{variables}` of the URL defaulting to the given values.
+pub fn generate_server(server: &str, defaults: &BTreeMap<String, String>) -> Server {
+    let (url, description) = match server.split_once(',') {
+        Some((url, description)) => (url.trim(), Some(description.trim().to_string())),
+        None => (server.trim(), None),
+    };
+    let variables: IndexMap<_, _> = SERVER_VAR_RE.captures_iter(url)
+        .map(|c| c[1].to_string())
+        .unique()
+        .map(|name| {
+            // OpenAPI requires a default for every variable
+            let default = defaults.get(&name).cloned().unwrap_or_else(|| {
+                eprintln!("warning: the variable {} of server {} has no default, pass --server-var {}=<value>", name, url, name);
+                String::new()
+            });
+            (name, ServerVariable { default, ..ServerVariable::default() })
+        })
+        .collect();
+
+    Server {
+        url: url.to_string(),
+        description,
+        variables: if variables.is_empty() { None } else { Some(variables) },
+        extensions: IndexMap::new(),
+    }
+}
+
+/// Returns the schema of `google.rpc.Status`, the body of gRPC errors transcoded to HTTP by gateways like grpc-gateway.
+pub fn grpc_status_schema() -> Schema {
+    let property = |schema_kind| ReferenceOr::boxed_item(Schema { schema_data: SchemaData::default(), schema_kind });
+    let mut properties = IndexMap::new();
+    properties.insert("code".to_string(), property(SchemaKind::Type(Type::Integer(IntegerType {
+        format: VariantOrUnknownOrEmpty::Item(IntegerFormat::Int32),
+        ..IntegerType::default()
+    }))));
+    properties.insert("message".to_string(), property(SchemaKind::Type(Type::String(StringType::default()))));
+    // the details are `google.protobuf.Any`s, which have a type and the fields of that type
+    let details = well_known_type_schema(".google.protobuf.Any", &GeneratorOptions::default()).unwrap();
+    properties.insert("details".to_string(), property(SchemaKind::Type(Type::Array(ArrayType {
+        items: ReferenceOr::boxed_item(details),
+        min_items: None,
+        max_items: None,
+        unique_items: false,
+    }))));
+
+    Schema {
+        schema_data: SchemaData {
+            description: Some("The error model of gRPC, with the status code, a message and further details.".to_string()),
+            ..SchemaData::default()
+        },
+        schema_kind: SchemaKind::Type(Type::Object(ObjectType { properties, ..ObjectType::default() })),
+    }
+}
+
+/// Returns the schema of a well-known type like `.google.protobuf.Timestamp`, following the protobuf JSON mapping.
+///
+/// Other types return `None`, since they are generated as schemas of their own.
+pub fn well_known_type_schema(type_name: &str, options: &GeneratorOptions) -> Option<Schema> {
+    let name = type_name.strip_prefix(".google.protobuf.")?;
+    let string_type = |format: VariantOrUnknownOrEmpty<StringFormat>| Type::String(StringType { format, ..StringType::default() });
+    let any = || ReferenceOr::boxed_item(Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Any(Default::default()) });
+    let schema_kind = match name {
+        "Timestamp" => SchemaKind::Type(string_type(VariantOrUnknownOrEmpty::Item(StringFormat::DateTime))),
+        "Duration" => SchemaKind::Type(string_type(VariantOrUnknownOrEmpty::Unknown("duration".to_string()))),
+        // field masks are the comma separated paths
+        "FieldMask" => SchemaKind::Type(string_type(VariantOrUnknownOrEmpty::Empty)),
+        "Struct" => SchemaKind::Type(Type::Object(ObjectType {
+            additional_properties: Some(AdditionalProperties::Any(true)),
+            ..ObjectType::default()
+        })),
+        "Value" => SchemaKind::Any(Default::default()),
+        "ListValue" => SchemaKind::Type(Type::Array(ArrayType { items: any(), min_items: None, max_items: None, unique_items: false })),
+        "Empty" => SchemaKind::Type(Type::Object(ObjectType::default())),
+        "Any" => {
+            let mut properties = IndexMap::new();
+            properties.insert("@type".to_string(), ReferenceOr::boxed_item(Schema {
+                schema_data: SchemaData::default(),
+                schema_kind: SchemaKind::Type(Type::String(StringType::default())),
+            }));
+            SchemaKind::Type(Type::Object(ObjectType {
+                properties,
+                additional_properties: Some(AdditionalProperties::Any(true)),
+                ..ObjectType::default()
+            }))
+        }
+        // wrappers are used to tell a missing value apart from the default, so they are nullable
+        wrapper => {
+            let scalar_type = match wrapper {
+                "BoolValue" => field_descriptor_proto::Type::Bool,
+                "StringValue" => field_descriptor_proto::Type::String,
+                "BytesValue" => field_descriptor_proto::Type::Bytes,
+                "Int32Value" => field_descriptor_proto::Type::Int32,
+                "UInt32Value" => field_descriptor_proto::Type::Uint32,
+                "Int64Value" => field_descriptor_proto::Type::Int64,
+                "UInt64Value" => field_descriptor_proto::Type::Uint64,
+                "FloatValue" => field_descriptor_proto::Type::Float,
+                "DoubleValue" => field_descriptor_proto::Type::Double,
+                _ => return None,
+            };
+            let field = FieldDescriptorProto { r#type: Some(scalar_type as i32), ..FieldDescriptorProto::default() };
+            // only messages, enums and groups can fail, which wrappers don't contain
+            return Some(Schema {
+                schema_data: SchemaData { nullable: true, ..SchemaData::default() },
+                schema_kind: SchemaKind::Type(scalar_type_to_schema(&field, options).ok()?),
+            });
+        }
+    };
+
+    Some(Schema { schema_data: SchemaData::default(), schema_kind })
+}
+
+/// Moves the comma separated properties of `order` to the front of an object schema, the other properties keep their order.
+pub fn order_properties(schema: &mut Schema, order: &str, message_name: &str) {
+    if let SchemaKind::Type(Type::Object(object_type)) = &mut schema.schema_kind {
+        let mut properties = IndexMap::new();
+        for name in order.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match object_type.properties.shift_remove_entry(name) {
+                Some((name, property)) => {
+                    properties.insert(name, property);
+                }
+                None => eprintln!("warning: unknown property {} in @order of message {}", name, message_name),
+            }
+        }
+        properties.extend(std::mem::take(&mut object_type.properties));
+        object_type.properties = properties;
+    }
+}
+
+/// Finds the schema of a property of an object schema, which is either a field or a member of a oneof.
+///
+/// The members of a oneof in the wrapped and union styles are found in the `oneOf` of its property, by their position in
+/// the `x-proto-oneof` extension. Siblings of `$ref` are ignored, so with `wrap` a referencing property is wrapped in an
+/// `allOf` to take them, otherwise it isn't found.
+pub fn property_schema<'a>(schema: &'a mut Schema, property: &str, wrap: bool) -> Option<&'a mut Schema> {
+    fn item_schema<T: BorrowMut<Schema> + From<Schema>>(property: &mut ReferenceOr<T>, wrap: bool) -> Option<&mut Schema> {
+        if let ReferenceOr::Reference { reference } = property {
+            if !wrap {
+                return None;
+            }
+            let all_of = vec![ReferenceOr::ref_(reference)];
+            *property = ReferenceOr::Item(T::from(Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::AllOf { all_of } }));
+        }
+        match property {
+            ReferenceOr::Item(item) => Some(item.borrow_mut()),
+            ReferenceOr::Reference { .. } => None,
+        }
+    }
+
+    let member = schema.schema_data.extensions.get("x-proto-oneof").and_then(|groups| {
+        groups.as_object()?.iter().find_map(|(oneof, members)| {
+            Some((oneof.clone(), members.as_array()?.iter().position(|member| member == property)?))
+        })
+    });
+    let object_type = match &mut schema.schema_kind {
+        SchemaKind::Type(Type::Object(object_type)) => object_type,
+        _ => return None,
+    };
+    // the members of an exclusive oneof are properties like any other
+    if object_type.properties.contains_key(property) {
+        return item_schema(object_type.properties.get_mut(property)?, wrap);
+    }
+
+    let (oneof, idx) = member?;
+    let member = match object_type.properties.get_mut(&oneof)? {
+        ReferenceOr::Item(item) => match &mut item.schema_kind {
+            SchemaKind::OneOf { one_of } => one_of.get_mut(idx)?,
+            _ => return None,
+        },
+        ReferenceOr::Reference { .. } => return None,
+    };
+    // the wrapped style puts every member into an object of its own
+    let wrapped = matches!(member, ReferenceOr::Item(Schema { schema_kind: SchemaKind::Type(Type::Object(wrapper)), .. })
+        if wrapper.properties.contains_key(property));
+    if !wrapped {
+        return item_schema(member, wrap);
+    }
+    match member {
+        ReferenceOr::Item(Schema { schema_kind: SchemaKind::Type(Type::Object(wrapper)), .. }) => item_schema(wrapper.properties.get_mut(property)?, wrap),
+        _ => None,
+    }
+}
+
+/// Sets the format of a string property (or the items of a repeated string property) from a `@format` annotation.
+///
+/// Only common formats like dates are allowed, anything else is ignored with a warning.
+pub fn format_property(schema: &mut Schema, property: &str, format: &str, message_name: &str) {
+    let format = match format {
+        "date" => VariantOrUnknownOrEmpty::Item(StringFormat::Date),
+        "date-time" => VariantOrUnknownOrEmpty::Item(StringFormat::DateTime),
+        "time" | "duration" => VariantOrUnknownOrEmpty::Unknown(format.to_string()),
+        _ => {
+            eprintln!("warning: ignoring unknown format {} of {}.{}, expected date, date-time, time or duration", format, message_name, property);
+            return;
+        }
+    };
+    let property_type = match property_schema(schema, property, false) {
+        Some(item) => match &mut item.schema_kind {
+            SchemaKind::Type(Type::Array(ArrayType { items: ReferenceOr::Item(items), .. })) => &mut items.schema_kind,
+            schema_kind => schema_kind,
+        },
+        _ => {
+            eprintln!("warning: ignoring @format of {}.{}, because it is not a string", message_name, property);
+            return;
+        }
+    };
+
+    match property_type {
+        SchemaKind::Type(Type::String(string_type)) => string_type.format = format,
+        _ => eprintln!("warning: ignoring @format of {}.{}, because it is not a string", message_name, property),
+    }
+}
+
+/// Applies the validation constraints of a field to its property in an object schema, if the property exists.
+///
+/// Only constraints matching the type of the property are used, references are left as they are.
+pub fn constrain_property(schema: &mut Schema, property: &str, constraints: &FieldConstraints) {
+    let property_type = match property_schema(schema, property, false).map(|item| &mut item.schema_kind) {
+        Some(SchemaKind::Type(property_type)) => property_type,
+        _ => return,
+    };
+
+    match property_type {
+        Type::String(string_type) => {
+            if let Some(rules) = &constraints.string {
+                string_type.min_length = rules.len.or(rules.min_len).map(|len| len as usize);
+                string_type.max_length = rules.len.or(rules.max_len).map(|len| len as usize);
+                // the pattern is passed on as it is, even if it isn't a valid regular expression
+                string_type.pattern = rules.pattern.clone();
+                // an explicit @format takes precedence
+                if let (Some(format), VariantOrUnknownOrEmpty::Empty) = (rules.format(), &string_type.format) {
+                    string_type.format = VariantOrUnknownOrEmpty::Unknown(format.to_string());
+                }
+            }
+        }
+        Type::Integer(integer_type) => {
+            if let Some(bounds) = constraints.integer_bounds() {
+                // a constant makes the range redundant
+                if let Some(constant) = bounds.constant {
+                    integer_type.enumeration = vec![constant];
+                } else {
+                    integer_type.minimum = bounds.gt.or(bounds.gte);
+                    integer_type.exclusive_minimum = bounds.gt.is_some();
+                    integer_type.maximum = bounds.lt.or(bounds.lte);
+                    integer_type.exclusive_maximum = bounds.lt.is_some();
+                }
+            }
+        }
+        Type::Number(number_type) => {
+            if let Some(bounds) = constraints.number_bounds() {
+                if let Some(constant) = bounds.constant {
+                    number_type.enumeration = vec![constant];
+                } else {
+                    number_type.minimum = bounds.gt.or(bounds.gte);
+                    number_type.exclusive_minimum = bounds.gt.is_some();
+                    number_type.maximum = bounds.lt.or(bounds.lte);
+                    number_type.exclusive_maximum = bounds.lt.is_some();
+                }
+            }
+        }
+        Type::Array(array_type) => {
+            if let Some(rules) = &constraints.repeated {
+                array_type.min_items = rules.min_items.map(|items| items as usize);
+                array_type.max_items = rules.max_items.map(|items| items as usize);
+                array_type.unique_items = rules.unique.unwrap_or(false);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Applies the `google.api.field_behavior` of a field to its property in an object schema, if the property exists.
+///
+/// `REQUIRED` fields become required (unless all properties are optional or they are members of a oneof), `OUTPUT_ONLY`
+/// fields `readOnly` and `INPUT_ONLY` fields `writeOnly`.
+pub fn apply_field_behavior(schema: &mut Schema, property: &str, behaviors: &[FieldBehavior], all_optional: bool) {
+    if behaviors.contains(&FieldBehavior::Required) && !all_optional {
+        if let SchemaKind::Type(Type::Object(object_type)) = &mut schema.schema_kind {
+            if object_type.properties.contains_key(property) && !object_type.required.iter().any(|r| r == property) {
+                object_type.required.push(property.to_string());
+            }
+        }
+    }
+
+    let read_only = behaviors.contains(&FieldBehavior::OutputOnly);
+    let write_only = behaviors.contains(&FieldBehavior::InputOnly);
+    if !read_only && !write_only {
+        return;
+    }
+    if let Some(item) = property_schema(schema, property, true) {
+        item.schema_data.read_only |= read_only;
+        item.schema_data.write_only |= write_only;
+    }
+}
+
+/// Adds a callback to the callbacks of the components, unless an equal one with the same name was already added.
+///
+/// Different callbacks with the same name would replace each other, so that fails the generation instead.
+pub fn add_callback(callbacks: &mut IndexMap<String, ReferenceOr<Callback>>, name: String, callback: Callback) -> Result<(), Error> {
+    match callbacks.get(&name) {
+        Some(ReferenceOr::Item(existing)) if *existing == callback => Ok(()),
+        Some(_) => Err(Error::CallbackCollision(name)),
+        None => {
+            callbacks.insert(name, ReferenceOr::Item(callback));
+            Ok(())
+        }
+    }
+}
+
+/// Returns the operation of a path item for an HTTP method (in upper case), or `None` if OpenAPI doesn't support it.
+pub fn operation_slot<'a>(path_item: &'a mut PathItem, method: &str) -> Option<&'a mut Option<Operation>> {
+    match method {
+        "GET" => Some(&mut path_item.get),
+        "POST" => Some(&mut path_item.post),
+        "PUT" => Some(&mut path_item.put),
+        "DELETE" => Some(&mut path_item.delete),
+        "PATCH" => Some(&mut path_item.patch),
+        "HEAD" => Some(&mut path_item.head),
+        "OPTIONS" => Some(&mut path_item.options),
+        _ => None,
+    }
+}
+
+/// Converts a name like `userManagement`, `UserService` or `user_management` to Title Case, like `User Management`.
+pub fn title_case(name: &str) -> String {
+    split_words(name).join(" ")
+}
+
+/// Splits a name like `userManagement`, `UserService` or `user_management` into capitalized words, like `User`, `Management`.
+fn split_words(name: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c == '_' || c == '-' || c.is_whitespace() {
+            previous = None;
+            continue;
+        }
+        let starts_word = match previous {
+            None => true,
+            Some(previous) => c.is_uppercase() && previous.is_lowercase(),
+        };
+        if starts_word {
+            words.push(c.to_uppercase().collect());
+        } else if let Some(word) = words.last_mut() {
+            word.push(c);
+        }
+        previous = Some(c);
+    }
+
+    words
+}
+
+/// Collects the names of all schemas referenced by `$ref` somewhere in a JSON value.
+fn collect_schema_refs(value: &serde_json::Value, refs: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(object) => {
+            for (key, value) in object {
+                match (key.as_str(), value) {
+                    ("$ref", serde_json::Value::String(reference)) => {
+                        if let Some((_, name)) = reference.split_once("#/components/schemas/") {
+                            refs.push(name.to_string());
+                        }
+                    }
+                    _ => collect_schema_refs(value, refs),
+                }
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                collect_schema_refs(value, refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the names of the schemas reachable from the references in `roots`, following references between schemas.
+///
+/// The names are in breadth-first order, so schemas referenced by `roots` come before the schemas only they reference.
+pub fn used_schemas(roots: &serde_json::Value, schemas: &IndexMap<String, ReferenceOr<Schema>>) -> Vec<String> {
+    let mut refs = Vec::new();
+    collect_schema_refs(roots, &mut refs);
+    let mut pending = VecDeque::from(refs);
+
+    let mut used = Vec::new();
+    while let Some(name) = pending.pop_front() {
+        if used.contains(&name) {
+            continue;
+        }
+        if let Some(schema) = schemas.get(&name) {
+            let mut refs = Vec::new();
+            collect_schema_refs(&serde_json::json!(schema), &mut refs);
+            pending.extend(refs);
+        }
+        used.push(name);
+    }
+
+    used
+}
+
+/// Returns `true` if a components object doesn't contain anything.
+pub fn components_are_empty(components: &Components) -> bool {
+    components.security_schemes.is_empty()
+        && components.responses.is_empty()
+        && components.parameters.is_empty()
+        && components.request_bodies.is_empty()
+        && components.headers.is_empty()
+        && components.schemas.is_empty()
+        && components.examples.is_empty()
+        && components.links.is_empty()
+        && components.callbacks.is_empty()
+        && components.extensions.is_empty()
+}
+
+/// Returns mutable references to the operations of a path item, in the same order as `path_item_operations`.
+pub fn path_item_operations_mut(path_item: &mut PathItem) -> Vec<&mut Operation> {
+    vec![
+        &mut path_item.get,
+        &mut path_item.put,
+        &mut path_item.post,
+        &mut path_item.delete,
+        &mut path_item.options,
+        &mut path_item.head,
+        &mut path_item.patch,
+        &mut path_item.trace,
+    ]
+    .into_iter()
+    .filter_map(Option::as_mut)
+    .collect()
+}
+
+/// Generates a path parameter with a type from a proto comment, like `int32`. Unknown types become strings.
+pub fn path_parameter(param: &str, param_type: &str) -> ReferenceOr<Parameter> {
+    ReferenceOr::Item(Parameter::Path {
+        style: PathStyle::Simple,
+        parameter_data: ParameterData {
+            name: param.to_string(),
+            description: None,
+            required: true,
+            deprecated: None,
+            format: ParameterSchemaOrContent::Schema(ReferenceOr::Item(Schema {
+                schema_data: SchemaData::default(),
+                schema_kind: SchemaKind::Type(match param_type {
+                    "string" => Type::String(StringType::default()),
+                    "int" => Type::Integer(IntegerType::default()),
+                    "int32" => integer_type(IntegerFormat::Int32),
+                    // like fields, unsigned 32-bit integers only fit into int64
+                    "int64" | "uint32" | "uint64" => integer_type(IntegerFormat::Int64),
+                    "bool" => Type::Boolean {},
+                    "float" => Type::Number(NumberType {
+                        format: VariantOrUnknownOrEmpty::Item(NumberFormat::Float),
+                        ..NumberType::default()
+                    }),
+                    "double" => Type::Number(NumberType {
+                        format: VariantOrUnknownOrEmpty::Item(NumberFormat::Double),
+                        ..NumberType::default()
+                    }),
+                    _ => {
+                        eprintln!("warning: path parameter {} has the unknown type {}, using string", param, param_type);
+                        Type::String(StringType::default())
+                    }
+                }),
+            })),
+            example: None,
+            examples: IndexMap::new(),
+            explode: None,
+            extensions: IndexMap::new(),
+        }
+    })
+}
+
+/// Returns the operation slots of a path item along with their lowercase HTTP method.
+pub fn path_item_slots_mut(path_item: &mut PathItem) -> Vec<(&'static str, &mut Option<Operation>)> {
+    vec![
+        ("get", &mut path_item.get),
+        ("put", &mut path_item.put),
+        ("post", &mut path_item.post),
+        ("delete", &mut path_item.delete),
+        ("options", &mut path_item.options),
+        ("head", &mut path_item.head),
+        ("patch", &mut path_item.patch),
+        ("trace", &mut path_item.trace),
+    ]
+}
+
+/// Moves the operations of a path item into the free slots of another one for the same path.
+///
+/// The summary and description of the existing path item are kept, unless it has none.
+pub fn merge_path_item(existing: &mut PathItem, mut path_item: PathItem) {
+    for ((_, slot), (_, operation)) in path_item_slots_mut(existing).into_iter().zip(path_item_slots_mut(&mut path_item)) {
+        if slot.is_none() {
+            *slot = operation.take();
+        }
+    }
+    if existing.summary.is_none() {
+        existing.summary = path_item.summary;
+    }
+    if existing.description.is_none() {
+        existing.description = path_item.description;
+    }
+}
+
+/// Returns the operations of a path item along with their lowercase HTTP method.
+pub fn path_item_operations(path_item: &PathItem) -> Vec<(&'static str, &Operation)> {
+    vec![
+        ("get", &path_item.get),
+        ("put", &path_item.put),
+        ("post", &path_item.post),
+        ("delete", &path_item.delete),
+        ("options", &path_item.options),
+        ("head", &path_item.head),
+        ("patch", &path_item.patch),
+        ("trace", &path_item.trace),
+    ]
+    .into_iter()
+    .filter_map(|(method, operation)| operation.as_ref().map(|operation| (method, operation)))
+    .collect()
+}
+
+/// Builds a flat list of all operations in the document, containing their method, path, operation ID and tags.
+pub fn generate_index(openapi: &OpenAPI) -> serde_json::Value {
+    let mut index = Vec::new();
+    for (path, path_item) in &openapi.paths {
+        let path_item = match path_item {
+            ReferenceOr::Item(path_item) => path_item,
+            ReferenceOr::Reference { .. } => continue,
+        };
+        for (method, operation) in path_item_operations(path_item) {
+            index.push(serde_json::json!({
+                "method": method,
+                "path": path,
+                "operationId": operation.operation_id,
+                "tags": operation.tags,
+            }));
+        }
+    }
+
+    serde_json::Value::Array(index)
+}
+
+/// Returns the number of operations in a document.
+pub fn count_operations(openapi: &OpenAPI) -> usize {
+    openapi.paths.values()
+        .map(|path_item| match path_item {
+            ReferenceOr::Item(path_item) => path_item_operations(path_item).len(),
+            ReferenceOr::Reference { .. } => 0,
+        })
+        .sum()
+}
+
+/// Collects all messages (including nested ones) of the given files, keyed by their fully qualified name like `.helloworld.HelloMessage`.
+pub fn collect_messages(files: &[FileDescriptorProto]) -> HashMap<String, DescriptorProto> {
+    fn collect(prefix: &str, messages: &[DescriptorProto], map: &mut HashMap<String, DescriptorProto>) {
+        for message in messages {
+            let name = format!("{}.{}", prefix, message.name());
+            collect(&name, &message.nested_type, map);
+            map.insert(name, message.clone());
+        }
+    }
+
+    let mut map = HashMap::new();
+    for file in files {
+        let prefix = if file.package().is_empty() { String::new() } else { format!(".{}", file.package()) };
+        collect(&prefix, &file.message_type, &mut map);
+    }
+
+    map
+}
+
+/// Determines the schema names of all messages and enums (including nested ones) of the given files,
+/// keyed by their fully qualified name like `.helloworld.HelloMessage`.
+///
+/// Nested types are named by the messages containing them, like `Outer_Inner`, so equally named nested types of
+/// different messages don't collide. With full naming, the package is prepended as well, like `helloworld_Outer_Inner`.
+/// Packages starting with the segments of `trim_package` are named without them, so with `acme` the schema of
+/// `acme.v1.User` is named `v1_User`. The names in `overrides` (from the `schema_name_option`) take precedence.
+pub fn collect_schema_names(
+    files: &[FileDescriptorProto],
+    naming: SchemaNaming,
+    trim_package: Option<&str>,
+    overrides: HashMap<String, String>,
+) -> HashMap<String, String> {
+    fn collect(prefix: &str, name_prefix: &str, separator: &str, messages: &[DescriptorProto], names: &mut HashMap<String, String>) {
+        for message in messages {
+            let full_name = format!("{}.{}", prefix, message.name());
+            let name = format!("{}{}", name_prefix, message.name());
+            for enum_type in &message.enum_type {
+                names.insert(format!("{}.{}", full_name, enum_type.name()), format!("{}{}{}", name, separator, enum_type.name()));
+            }
+            collect(&full_name, &format!("{}{}", name, separator), separator, &message.nested_type, names);
+            names.insert(full_name, name);
+        }
+    }
+
+    // nested types are joined by underscores, except for the package naming which keeps the proto names
+    let separator = if naming == SchemaNaming::Package { "." } else { "_" };
+    let mut names = HashMap::new();
+    for file in files {
+        let prefix = if file.package().is_empty() { String::new() } else { format!(".{}", file.package()) };
+        // only whole segments are trimmed, so `acme` doesn't trim `acmecorp.v1`
+        let package = match trim_package.map(|trim| trim.trim_matches('.')) {
+            Some(trim) if file.package() == trim => "",
+            Some(trim) => file.package().strip_prefix(trim).and_then(|rest| rest.strip_prefix('.')).unwrap_or(file.package()),
+            None => file.package(),
+        };
+        let name_prefix = match naming {
+            _ if package.is_empty() => String::new(),
+            SchemaNaming::Short => String::new(),
+            SchemaNaming::Full => format!("{}_", package.replace('.', "_")),
+            SchemaNaming::Package => format!("{}.", package),
+        };
+        for enum_type in &file.enum_type {
+            names.insert(format!("{}.{}", prefix, enum_type.name()), format!("{}{}", name_prefix, enum_type.name()));
+        }
+        collect(&prefix, &name_prefix, separator, &file.message_type, &mut names);
+    }
+    names.extend(overrides);
+
+    names
+}
+
+/// Collects the fully qualified names of all enums (including nested ones) of the given files, like `.helloworld.Status`.
+pub fn collect_enum_names(files: &[FileDescriptorProto]) -> Vec<String> {
+    fn collect(prefix: &str, messages: &[DescriptorProto], names: &mut Vec<String>) {
+        for message in messages {
+            let name = format!("{}.{}", prefix, message.name());
+            names.extend(message.enum_type.iter().map(|enum_type| format!("{}.{}", name, enum_type.name())));
+            collect(&name, &message.nested_type, names);
+        }
+    }
+
+    let mut names = Vec::new();
+    for file in files {
+        let prefix = if file.package().is_empty() { String::new() } else { format!(".{}", file.package()) };
+        names.extend(file.enum_type.iter().map(|enum_type| format!("{}.{}", prefix, enum_type.name())));
+        collect(&prefix, &file.message_type, &mut names);
+    }
+
+    names
+}
+
+/// Normalizes the `path_prefix` option to a path like `/tenants/{tenant}` (empty for `/`), without duplicate slashes,
+/// and returns it along with the names and types of its parameters.
+pub fn split_path_prefix(prefix: &str) -> (String, Vec<(String, String)>) {
+    let parameters = PREFIX_PARAM_RE.captures_iter(prefix).map(|c| {
+        (c["param"].to_string(), c.name("param_type").map_or("string", |param_type| param_type.as_str()).to_string())
+    }).collect();
+    let path = PREFIX_PARAM_RE.replace_all(prefix, "{$param}")
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| format!("/{}", segment))
+        .collect();
+
+    (path, parameters)
 }
 
 /// Converts a query path from a proto comment to a valid OpenAPI path.
@@ -94,18 +899,10 @@ impl TryFrom<&String> for OpenAPIPathInfo {
             (param, param_type)
         }).collect();
 
-        let mut include_body = BODY_RE.is_match(value);
-        if include_body {
-            let prefix = BODY_RE.captures(value).unwrap().get(1).unwrap().as_str();
-            if prefix == "+" {
-                include_body = true;
-            } else {
-                include_body = false;
-            }
-        } else {
-            // if the regex doesn't match, default to true
-            include_body = true;
-        }
+        let explicit_body = BODY_RE.is_match(value);
+        // if the regex doesn't match, default to true (is_none_or would need Rust 1.82)
+        #[allow(clippy::unnecessary_map_or)]
+        let include_body = BODY_RE.captures(value).map_or(true, |c| &c[1] == "+");
 
         let mut tags = Vec::new();
         if TAG_RE.is_match(value) {
@@ -113,208 +910,1336 @@ impl TryFrom<&String> for OpenAPIPathInfo {
             tags = tag_str.as_str().split(',').map(str::trim).map(str::to_owned).collect();
         }
 
+        let errors = ERRORS_RE.captures(value).map_or_else(Vec::new, |c| {
+            c[1].split(',')
+                .filter_map(|error| {
+                    let (code, message) = error.trim().split_once('=')?;
+                    Some((code.parse().ok()?, message.to_string()))
+                })
+                .collect()
+        });
+
+        let success_status = STATUS_RE.captures(value).and_then(|c| c[1].parse().ok());
+        let query = QUERY_RE.captures(value)
+            .map_or_else(Vec::new, |c| c[1].split(',').map(str::trim).map(str::to_owned).collect());
+
         Ok(OpenAPIPathInfo {
             path,
             method,
             parameters,
             include_body,
+            explicit_body,
+            body_field: None,
             tags,
+            errors,
+            success_status,
+            query,
         })
     }
 }
 
-/// Contains information about the generation of the proto files.
-pub struct OpenAPIGenerator<'a> {
-    pub config: &'a mut Config,
-    source_info: SourceCodeInfo,
-    path: Vec<i32>,
+/// How a proto `oneof` is represented in the schema of its message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OneofStyle {
+    /// A `oneOf` of objects, each containing a single property named like the member field.
+    #[default]
+    Wrapped,
+    /// A `oneOf` of the member types themselves, like a tagged union.
+    Union,
+    /// Every member field becomes an optional property of the message, with a description noting that they are mutually exclusive.
+    Exclusive,
 }
 
-impl<'a> OpenAPIGenerator<'a> {
-    /// Returns the current location in the proto file.
-    /// This is not accurate!
-    pub fn location(&self) -> &Location {
-        let idx = self
-            .source_info
-            .location
-            .binary_search_by_key(&&self.path[..], |location| &location.path[..])
-            .unwrap();
+impl FromStr for OneofStyle {
+    type Err = String;
 
-        &self.source_info.location[idx]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrapped" => Ok(OneofStyle::Wrapped),
+            "union" => Ok(OneofStyle::Union),
+            "exclusive" => Ok(OneofStyle::Exclusive),
+            _ => Err(format!("unknown oneof style '{}'", s)),
+        }
     }
+}
 
-    /// Generates an OpenAPI object, which can be directly serialized to YAML.
-    pub fn generate(
-        config: &mut Config,
-        protos: &[impl AsRef<Path>],
-        includes: &[impl AsRef<Path>],
-    ) -> OpenAPI {
-        let files = config.get_descriptor(protos, includes);
-        let files = files.unwrap().file;
-        let mut openapi = OpenAPI::default();
-
-        let mut schema_map: IndexMap<String, ReferenceOr<Schema>> = IndexMap::new();
-        for file in files {
-            let mut source_info = file.source_code_info.clone().expect("");
-            source_info.location.retain(|location| {
-                let len = location.path.len();
-                len > 0 && len % 2 == 0
-            });
-            source_info
-                .location
-                .sort_by_key(|location| location.path.clone());
+/// Where references to components point to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RefStyle {
+    /// References into the generated document itself, like `#/components/schemas/X`.
+    #[default]
+    Internal,
+    /// References into a sibling file bundled with the document, like `./components.yaml#/components/schemas/X`.
+    Bundled,
+    /// References to an absolute URL, like `https://example.com/api.yaml#/components/schemas/X`.
+    External,
+}
 
-            let mut gen = OpenAPIGenerator {
-                config,
-                source_info,
-                path: Vec::new(),
-            };
+impl FromStr for RefStyle {
+    type Err = String;
 
-            gen.path.push(4);
-            for (idx, message) in file.message_type.into_iter().enumerate() {
-                // generate messages as schemas
-                gen.path.push(idx as i32);
-                println!("generating message {}", message.name());
-                let schema = gen.generate_schema_recursive(message, 0);
-                schema_map.extend(schema.into_iter().map(|(k, v)| (k, ReferenceOr::Item(v))));
-                gen.path.pop();
-            }
-            gen.path.pop();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "internal" => Ok(RefStyle::Internal),
+            "bundled" => Ok(RefStyle::Bundled),
+            "external" => Ok(RefStyle::External),
+            _ => Err(format!("unknown ref style '{}'", s)),
+        }
+    }
+}
 
-            gen.path.push(5);
-            for (idx, enum_type) in file.enum_type.iter().enumerate() {
-                gen.path.push(idx as i32);
-                println!("generating enum {}", enum_type.name());
-                let schema = gen.generate_enum_schema(&enum_type.value);
-                schema_map.insert(enum_type.name().to_string(), ReferenceOr::Item(schema));
-                gen.path.pop();
-            }
-            gen.path.pop();
+/// The order of the schemas in the components of the document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SchemaSort {
+    /// The order in which the messages and enums are declared in the proto files, nested ones following their parent.
+    #[default]
+    Declaration,
+    /// Alphabetical order of the schema names.
+    Alpha,
+    /// The order in which the schemas are first referenced from the operations, breadth-first.
+    Usage,
+}
 
-            gen.path.push(6);
-            for (idx, service) in file.service.into_iter().enumerate() {
-                // generate services as paths
-                gen.path.push(idx as i32);
-                println!("generating service {}", service.name());
-                let svc = gen.generate_service(service);
-
-                let method_infos = svc.methods.into_iter()
-                    .map(|m| {
-                        let input_type = m.input_proto_type;
-                        let output_type = m.output_proto_type;
-                        let mut possible_paths = Vec::new();
-                        for comment in &m.comments.leading {
-                            let path_def = OpenAPIPathInfo::try_from(comment);
-                            if let Ok(path_def) = path_def { possible_paths.push(path_def) }
-                        }
-                        (input_type, output_type, possible_paths)
-                    }).collect_vec();
-                // collect all possible unique paths
-                let mut paths = HashMap::new();
-                for (input_type, output_type, possible_paths) in method_infos {
-                    for path in possible_paths {
-                        if !paths.contains_key(&path.path) {
-                            let mut path_info = Vec::new();
-                            let str_path = path.path.clone();
-                            path_info.push((input_type.clone(), output_type.clone(), path));
-                            paths.insert(str_path, path_info);
-                        } else {
-                            let path_info = paths.get_mut(&path.path).unwrap();
-                            path_info.push((input_type.clone(), output_type.clone(), path));
-                        }
-                    }
-                }
+impl FromStr for SchemaSort {
+    type Err = String;
 
-                for (path, path_info) in paths {
-                    println!("generating path {}", path);
-                    let path_item = gen.generate_path(&path_info);
-                    openapi.paths.insert(path_to_openapi_path(&path), ReferenceOr::Item(path_item));
-                }
-                gen.path.pop();
-            }
-            gen.path.pop();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "declaration" => Ok(SchemaSort::Declaration),
+            "alpha" => Ok(SchemaSort::Alpha),
+            "usage" => Ok(SchemaSort::Usage),
+            _ => Err(format!("unknown schema sort '{}'", s)),
         }
-        openapi.components = Some(Components {
-            security_schemes: IndexMap::new(),
-            responses: IndexMap::new(),
-            parameters: IndexMap::new(),
-            request_bodies: IndexMap::new(),
-            headers: IndexMap::new(),
+    }
+}
+
+/// How the names of the schemas are derived from the proto type names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SchemaNaming {
+    /// The name of the message or enum itself, like `User`.
+    #[default]
+    Short,
+    /// The fully qualified name joined by underscores, like `acme_v1_User`.
+    Full,
+    /// The fully qualified name itself, like `acme.v1.User`, for tools which keep the package namespacing.
+    Package,
+}
+
+impl FromStr for SchemaNaming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "short" => Ok(SchemaNaming::Short),
+            "full" => Ok(SchemaNaming::Full),
+            "package" => Ok(SchemaNaming::Package),
+            _ => Err(format!("unknown schema naming '{}'", s)),
+        }
+    }
+}
+
+/// How the operation ID of an operation is derived from the names of its service and method.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OperationIdStyle {
+    /// The proto names joined by an underscore, like `Greeter_SayHello`.
+    #[default]
+    Proto,
+    /// camelCase, like `greeterSayHello`.
+    Camel,
+    /// snake_case, like `greeter_say_hello`.
+    Snake,
+}
+
+impl FromStr for OperationIdStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proto" => Ok(OperationIdStyle::Proto),
+            "camel" => Ok(OperationIdStyle::Camel),
+            "snake" => Ok(OperationIdStyle::Snake),
+            _ => Err(format!("unknown operation ID style '{}'", s)),
+        }
+    }
+}
+
+impl OperationIdStyle {
+    /// Returns the operation ID of a method, given the short names of the service and method.
+    pub fn operation_id(self, service: &str, method: &str) -> String {
+        match self {
+            OperationIdStyle::Proto => format!("{}_{}", service, method),
+            OperationIdStyle::Camel => {
+                let id = split_words(service).into_iter().chain(split_words(method)).collect::<String>();
+                let mut chars = id.chars();
+                chars.next().map(|first| first.to_lowercase().chain(chars).collect()).unwrap_or_default()
+            }
+            OperationIdStyle::Snake => split_words(service).into_iter().chain(split_words(method)).map(|word| word.to_lowercase()).join("_"),
+        }
+    }
+
+    /// Returns the `n`th variant of an operation ID, used to keep the IDs of methods with multiple routes unique.
+    pub fn numbered(self, id: &str, n: usize) -> String {
+        match self {
+            OperationIdStyle::Camel => format!("{}{}", id, n),
+            OperationIdStyle::Proto | OperationIdStyle::Snake => format!("{}_{}", id, n),
+        }
+    }
+}
+
+/// The case of operation IDs, applied after they are built according to the `OperationIdStyle`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OperationIdCase {
+    /// camelCase, like `greeterSayHello`.
+    Camel,
+    /// PascalCase, like `GreeterSayHello`.
+    Pascal,
+    /// snake_case, like `greeter_say_hello`.
+    Snake,
+}
+
+impl FromStr for OperationIdCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "camel" => Ok(OperationIdCase::Camel),
+            "pascal" => Ok(OperationIdCase::Pascal),
+            "snake" => Ok(OperationIdCase::Snake),
+            _ => Err(format!("unknown operation ID case '{}'", s)),
+        }
+    }
+}
+
+impl OperationIdCase {
+    /// Converts an operation ID to this case, splitting it into words at underscores and changes of case.
+    pub fn apply(self, id: &str) -> String {
+        let words = split_words(id);
+        match self {
+            OperationIdCase::Camel => {
+                let id = words.concat();
+                let mut chars = id.chars();
+                chars.next().map(|first| first.to_lowercase().chain(chars).collect()).unwrap_or_default()
+            }
+            OperationIdCase::Pascal => words.concat(),
+            OperationIdCase::Snake => words.iter().map(|word| word.to_lowercase()).join("_"),
+        }
+    }
+
+    /// Returns the `n`th variant of an operation ID, like `OperationIdStyle::numbered`.
+    pub fn numbered(self, id: &str, n: usize) -> String {
+        match self {
+            OperationIdCase::Camel | OperationIdCase::Pascal => format!("{}{}", id, n),
+            OperationIdCase::Snake => format!("{}_{}", id, n),
+        }
+    }
+}
+
+/// The kinds of elements which need a description with the `require_descriptions` option.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DescriptionKind {
+    Messages,
+    Fields,
+    EnumValues,
+    Operations,
+}
+
+impl DescriptionKind {
+    /// Every kind of element, used if no kinds are given.
+    pub const ALL: [DescriptionKind; 4] = [DescriptionKind::Messages, DescriptionKind::Fields, DescriptionKind::EnumValues, DescriptionKind::Operations];
+}
+
+impl FromStr for DescriptionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "messages" => Ok(DescriptionKind::Messages),
+            "fields" => Ok(DescriptionKind::Fields),
+            "enum-values" => Ok(DescriptionKind::EnumValues),
+            "operations" => Ok(DescriptionKind::Operations),
+            _ => Err(format!("unknown element kind '{}'", s)),
+        }
+    }
+}
+
+/// The file bundled references point to, if no `ref_base` is given.
+pub const DEFAULT_BUNDLED_REF_BASE: &str = "./components.yaml";
+
+/// The format in which an OpenAPI document is written.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Yaml,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(OutputFormat::Yaml),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Returns the format matching the extension of an output path, JSON for `.json` and YAML otherwise.
+    pub fn from_path(path: &Path) -> OutputFormat {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => OutputFormat::Json,
+            _ => OutputFormat::Yaml,
+        }
+    }
+}
+
+/// Returns the source info of a file, sorted by path so locations can be found using a binary search.
+///
+/// Only locations of elements which can have comments are kept.
+fn sorted_source_info(file: &FileDescriptorProto) -> SourceCodeInfo {
+    let mut source_info = file.source_code_info.clone().unwrap_or_else(|| {
+        eprintln!("warning: {} has no source info, comments will be missing from the documentation", file.name());
+        SourceCodeInfo::default()
+    });
+    source_info.location.retain(|location| {
+        let len = location.path.len();
+        len > 0 && len % 2 == 0
+    });
+    source_info
+        .location
+        .sort_by_key(|location| location.path.clone());
+
+    source_info
+}
+
+/// Returns the comment at the top of a proto file, which is the comment before the `syntax` (or `package`) statement.
+///
+/// With `skip_license`, comment blocks mentioning a license or copyright are left out.
+fn file_description(file: &FileDescriptorProto, skip_license: bool) -> Option<String> {
+    let source_info = file.source_code_info.as_ref()?;
+    // 12 is the syntax statement, 2 the package statement
+    let location = [12, 2].iter()
+        .find_map(|field| source_info.location.iter().find(|location| location.path == [*field]))?;
+
+    let blocks = location.leading_detached_comments.iter()
+        .chain(&location.leading_comments)
+        .filter(|block| {
+            let lowercase = block.to_lowercase();
+            !skip_license || !(lowercase.contains("license") || lowercase.contains("copyright"))
+        })
+        .map(|block| block.lines().map(|line| line.strip_prefix(' ').unwrap_or(line)).join("\n").trim().to_string())
+        .filter(|block| !block.is_empty())
+        .collect_vec();
+
+    Some(blocks.join("\n\n")).filter(|description| !description.is_empty())
+}
+
+/// Returns the package of a schema, if it was generated with the `group_schemas_by_package` option.
+fn schema_package(schema: &ReferenceOr<Schema>) -> Option<&str> {
+    match schema {
+        ReferenceOr::Item(schema) => schema.schema_data.extensions.get("x-proto-package")?.as_str(),
+        ReferenceOr::Reference { .. } => None,
+    }
+}
+
+/// Inserts a comment like `# package: acme.v1` before each group of schemas from the same package into a YAML document.
+///
+/// Only schemas generated with the `group_schemas_by_package` option have a package, otherwise the document is unchanged.
+fn insert_package_comments(yaml: &str, openapi: &OpenAPI) -> String {
+    let packages: HashMap<&str, &str> = openapi.components.iter()
+        .flat_map(|components| &components.schemas)
+        .filter_map(|(name, schema)| Some((name.as_str(), schema_package(schema)?)))
+        .collect();
+    if packages.is_empty() {
+        return yaml.to_string();
+    }
+
+    let mut lines = Vec::new();
+    let mut in_schemas = false;
+    let mut current_package = None;
+    for line in yaml.lines() {
+        let indent = line.len() - line.trim_start().len();
+        if line == "  schemas:" {
+            in_schemas = true;
+        } else if in_schemas && indent <= 2 {
+            in_schemas = false;
+        } else if in_schemas && indent == 4 {
+            let name = line.trim().trim_end_matches(':').trim_matches('"');
+            if let Some(package) = packages.get(name).filter(|package| current_package != Some(**package)) {
+                lines.push(match *package {
+                    "" => "    # no package".to_string(),
+                    package => format!("    # package: {}", package),
+                });
+                current_package = Some(*package);
+            }
+        }
+        lines.push(line.to_string());
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Serializes an OpenAPI document to a writer in the given format.
+pub fn write_openapi(openapi: &OpenAPI, format: OutputFormat, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Yaml => {
+            let yaml = serde_yaml::to_string(openapi)?;
+            writer.write_all(insert_package_comments(&yaml, openapi).as_bytes())?;
+        }
+        OutputFormat::Json => serde_json::to_writer_pretty(writer, openapi)?,
+    }
+
+    Ok(())
+}
+
+/// Options which influence how the OpenAPI document is generated.
+///
+/// These can also be read from a configuration file, see `FileConfig`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GeneratorOptions {
+    /// Use the `json_name` of proto fields as property names instead of the field names.
+    pub json_names: bool,
+    /// A prefix which is prepended to every schema name and reference.
+    pub prefix_schemas: Option<String>,
+    /// Flatten the input message of GET methods into query parameters.
+    pub query_params: bool,
+    /// How many levels of nested messages are flattened into query parameters. Defaults to `DEFAULT_QUERY_DEPTH`.
+    pub query_depth: Option<usize>,
+    /// Tags which are added to every operation, in addition to the tags of the path comment.
+    pub additional_tags: Vec<String>,
+    /// How oneofs are represented.
+    pub oneof_style: OneofStyle,
+    /// Serialized `FileDescriptorSet`s which are merged and used instead of running protoc on the proto files.
+    pub descriptor_sets: Vec<PathBuf>,
+    /// Whether a custom status of the successful response replaces the `200` response or is added next to it.
+    /// Defaults to `true`.
+    pub replace_default_status: Option<bool>,
+    /// Writes the `FileDescriptorSet` produced by protoc to this path, for debugging.
+    pub dump_descriptor: Option<PathBuf>,
+    /// Caches the `FileDescriptorSet` produced by protoc at this path. protoc is skipped as long as it would be run with
+    /// the same arguments and no proto file (including the ones in the include directories) was modified after the
+    /// cache was written.
+    pub descriptor_cache: Option<PathBuf>,
+    /// Write a JSON file mapping the fully qualified names of messages, enums and methods to their location in the document.
+    pub emit_mapping: Option<PathBuf>,
+    /// A prefix which is removed from every path, if present.
+    pub strip_path_prefix: Option<String>,
+    /// A prefix which is added to every path, like `/api` or `/tenants/{tenant}`.
+    /// Its parameters become path parameters of every path, typed like in comments (`{tenant:int}`) or strings.
+    pub path_prefix: Option<String>,
+    /// Use the names of enum values instead of their integer values, like the proto JSON mapping does.
+    pub enum_as_string: bool,
+    /// Accept both the integer value and the name of enum values, like the proto JSON parser does.
+    /// Takes precedence over `enum_as_string`.
+    pub enum_as_string_or_int: bool,
+    /// Render enums with a single value as a `const` in OpenAPI 3.1. This has no effect on the output until a 3.1
+    /// mode exists: the documents are OpenAPI 3.0, so such enums keep their one-element `enum` array, with a warning.
+    pub collapse_single_value_enums: bool,
+    /// Tag operations without an explicit tag with the first segment of their path.
+    pub tag_by_path: bool,
+    /// Add an `x-api-index` extension listing all operations to the document.
+    pub emit_index: bool,
+    /// How many levels of nested message definitions are turned into schemas, `None` for all of them.
+    pub flatten_depth: Option<usize>,
+    /// Shared responses by status code, which are added to the components and referenced using `@responses`.
+    pub responses: BTreeMap<u16, SharedResponse>,
+    /// The URLs of the servers of the API, with environment variables already expanded.
+    /// A description can follow the URL after a comma, like `https://api.example.com,Production`.
+    pub servers: Vec<String>,
+    /// The default values of the `{variables}` in server URLs, by name.
+    pub server_variables: BTreeMap<String, String>,
+    /// Expand undefined environment variables in server URLs to an empty string instead of failing.
+    pub allow_missing_env: bool,
+    /// Disallow properties which aren't fields of the message, unless the message is annotated with `@open`.
+    pub no_additional_properties: bool,
+    /// Maps suffixes of response message names to the status code of the successful response, like `Created` to 201.
+    pub status_from_response_name: BTreeMap<String, u16>,
+    /// Leave comments mentioning a license or copyright out of the description taken from the top of the proto file.
+    pub skip_license_comment: bool,
+    /// Tag operations without an explicit tag with the name of their proto file.
+    pub tag_by_file: bool,
+    /// The kinds of elements of the proto files which fail the generation if they have no description.
+    pub require_descriptions: Vec<DescriptionKind>,
+    /// Don't mark any property as required, even if the field is always present.
+    pub all_optional: bool,
+    /// Don't mark repeated fields and maps as required, even though they are always present.
+    pub optional_repeated: bool,
+    /// Add a `google.rpc.Status` schema and use it as the default response of every operation.
+    pub grpc_error_model: bool,
+    /// Represent booleans as strings which are either `true` or `false`.
+    pub bool_as_string: bool,
+    /// Represent 64-bit integers as strings, like the protobuf JSON mapping does.
+    pub int64_as_string: bool,
+    /// Fail the generation on malformed field types instead of falling back to objects.
+    pub strict: bool,
+    /// Use the comment of the service as the description of the paths of its methods.
+    pub path_description_from_service: bool,
+    /// Add an `x-displayName` in Title Case to every tag, without changing the tag names.
+    pub title_case_tags: bool,
+    /// Accept a document without any operations, which usually means the route comments weren't recognized.
+    pub allow_empty_paths: bool,
+    /// Where references to components point to.
+    pub ref_style: RefStyle,
+    /// The file or URL references point to for the bundled and external `ref_style`.
+    pub ref_base: Option<String>,
+    /// Prefix the paths of every file with the version segment of its package, like `/v1` for `acme.v1`.
+    pub version_paths: bool,
+    /// Leave out schemas which aren't referenced by any operation, directly or indirectly.
+    pub omit_unused_schemas: bool,
+    /// Leave out the schemas of files which were only imported by the proto files, unless they are referenced.
+    pub no_import_schemas: bool,
+    /// Add a request body to GET operations whose comment contains `+ BODY`.
+    pub allow_get_body: bool,
+    /// The field number of a string extension of `MessageOptions` which overrides the schema name of a message.
+    pub schema_name_option: Option<u64>,
+    /// The field number of a string extension of `MessageOptions` which contains a JSON example of a message.
+    pub example_option: Option<u64>,
+    /// The order of the schemas in the components of the document.
+    pub schema_sort: SchemaSort,
+    /// How schema names are derived from the proto type names.
+    pub schema_naming: SchemaNaming,
+    /// A package prefix dropped from the schema names of the `full` and `package` naming, like `acme` or `acme.v1`.
+    pub trim_leading_package: Option<String>,
+    /// Cluster the schemas by their proto package, marked by an `x-proto-package` extension and a YAML comment.
+    pub group_schemas_by_package: bool,
+    /// How operation IDs are derived from the service and method names.
+    pub operation_id_style: OperationIdStyle,
+    /// The case operation IDs are converted to after they are built, they are left as they are if missing.
+    pub operation_id_case: Option<OperationIdCase>,
+}
+
+/// A response which is defined once and can be referenced by multiple operations.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SharedResponse {
+    /// The description of the response.
+    pub description: String,
+    /// The proto message (or schema) of the response body, if it has one.
+    pub schema: Option<String>,
+    /// The content type of the response body, like `application/problem+json`. Defaults to `application/json`.
+    pub content_type: Option<String>,
+}
+
+/// The default depth up to which nested messages are flattened into query parameters.
+pub const DEFAULT_QUERY_DEPTH: usize = 3;
+
+/// Contains information about the generation of the proto files.
+pub struct OpenAPIGenerator<'a> {
+    pub config: &'a mut Config,
+    pub options: &'a GeneratorOptions,
+    /// All messages known to protoc, keyed by their fully qualified name.
+    messages: &'a HashMap<String, DescriptorProto>,
+    source_info: SourceCodeInfo,
+    /// The schema names of all messages and enums (without prefix), keyed by their fully qualified name.
+    schema_names: &'a HashMap<String, String>,
+    /// The validation constraints of the fields of the current file, keyed by their path.
+    constraints: HashMap<Vec<i32>, FieldConstraints>,
+    /// The `google.api.field_behavior` of the fields of the current file, keyed by their path.
+    field_behaviors: HashMap<Vec<i32>, Vec<FieldBehavior>>,
+    /// The fully qualified name of the package or message containing the current element, like `.helloworld`.
+    scope: String,
+    /// Whether the current file uses proto3 syntax, where singular fields without `optional` are always present.
+    proto3: bool,
+    path: Vec<i32>,
+}
+
+impl<'a> OpenAPIGenerator<'a> {
+    /// Returns the current location in the proto file.
+    ///
+    /// If there is no source info for the current location, an empty location is returned.
+    pub fn location(&self) -> &Location {
+        match self
+            .source_info
+            .location
+            .binary_search_by_key(&&self.path[..], |location| &location.path[..])
+        {
+            Ok(idx) => &self.source_info.location[idx],
+            Err(_) => &EMPTY_LOCATION,
+        }
+    }
+
+    /// Returns the serialized `FileDescriptorSet` to generate from, merged from the `descriptor_sets` if there are any,
+    /// otherwise produced by protoc from the proto files.
+    fn raw_descriptor(
+        config: &mut Config,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+        options: &GeneratorOptions,
+    ) -> Result<Vec<u8>, Error> {
+        if !options.descriptor_sets.is_empty() {
+            return prost_light::read_descriptor_sets(&options.descriptor_sets).map_err(Error::DescriptorSets);
+        }
+        let cache = options.descriptor_cache.as_ref();
+        if let Some(raw_files) = cache.and_then(|cache| prost_light::read_cached_descriptor(cache, protos, includes)) {
+            return Ok(raw_files);
+        }
+
+        let raw_files = config.get_raw_descriptor(protos, includes).map_err(Error::Protoc)?;
+        if let Some(cache) = cache {
+            if let Err(err) = prost_light::write_cached_descriptor(cache, protos, includes, &raw_files) {
+                eprintln!("warning: failed to write the descriptor cache {}: {}", cache.display(), err);
+            }
+        }
+
+        Ok(raw_files)
+    }
+
+    /// Generates an OpenAPI object, which can be directly serialized to YAML.
+    pub fn generate(
+        config: &mut Config,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+        options: &GeneratorOptions,
+    ) -> Result<OpenAPI, Error> {
+        let raw_files = Self::raw_descriptor(config, protos, includes, options)?;
+        let files = FileDescriptorSet::decode(&*raw_files)?;
+        if let Some(dump_path) = &options.dump_descriptor {
+            if let Err(err) = std::fs::write(dump_path, &raw_files) {
+                eprintln!("warning: failed to write descriptor set to {}: {}", dump_path.display(), err);
+            }
+        }
+        let files = files.file;
+        let messages = collect_messages(&files);
+        let mut openapi = OpenAPI::default();
+        // the top comment of the first proto file describes the whole document
+        openapi.info.description = protos.first()
+            .and_then(|proto| files.iter().find(|file| proto.as_ref().ends_with(file.name())))
+            .and_then(|file| file_description(file, options.skip_license_comment));
+
+        let mut schema_map: IndexMap<String, ReferenceOr<Schema>> = IndexMap::new();
+        let mut file_tags = Vec::new();
+        let mut callbacks = IndexMap::new();
+        let mut method_operations = BTreeMap::new();
+        let mut operation_ids = HashSet::new();
+        let (path_prefix, prefix_parameters) = split_path_prefix(options.path_prefix.as_deref().unwrap_or_default());
+        // messages and enums of files imported by multiple protos are only generated once
+        let mut generated_types = HashSet::new();
+        let mut import_schemas = HashSet::new();
+        let enum_names = collect_enum_names(&files);
+        let mut file_constraints = extensions::collect_constraints(&raw_files).into_iter();
+        let mut file_field_behaviors = extensions::collect_field_behaviors(&raw_files).into_iter();
+        let mut file_http_rules = extensions::collect_http_rules(&raw_files).into_iter();
+        let schema_names = collect_schema_names(&files, options.schema_naming, options.trim_leading_package.as_deref(), match options.schema_name_option {
+            Some(number) => extensions::collect_message_options(&raw_files, number),
+            None => HashMap::new(),
+        });
+        for file in files {
+            let file_stem = Path::new(file.name()).file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            // packages without a version segment are left unprefixed
+            let version_prefix = file.package().split('.').rev()
+                .find(|segment| VERSION_RE.is_match(segment))
+                .filter(|_| options.version_paths)
+                .map(|version| format!("/{}", version));
+            if options.tag_by_file {
+                file_tags.push((file_stem.clone(), file_description(&file, options.skip_license_comment)));
+            }
+            let mut gen = OpenAPIGenerator {
+                config,
+                options,
+                messages: &messages,
+                schema_names: &schema_names,
+                source_info: sorted_source_info(&file),
+                constraints: file_constraints.next().unwrap_or_default(),
+                field_behaviors: file_field_behaviors.next().unwrap_or_default(),
+                scope: if file.package().is_empty() { String::new() } else { format!(".{}", file.package()) },
+                proto3: file.syntax() == "proto3",
+                path: Vec::new(),
+            };
+
+            let http_rules = file_http_rules.next().unwrap_or_default();
+
+            // without protos (like with descriptor sets), every file is an input
+            let imported = !protos.is_empty() && !protos.iter().any(|proto| proto.as_ref().ends_with(file.name()));
+            let package = file.package().to_string();
+            let with_package = |mut schema: Schema| {
+                if options.group_schemas_by_package {
+                    schema.schema_data.extensions.insert("x-proto-package".to_string(), package.clone().into());
+                }
+                ReferenceOr::Item(schema)
+            };
+
+            gen.path.push(4);
+            for (idx, message) in file.message_type.into_iter().enumerate() {
+                if !generated_types.insert(format!("{}.{}", gen.scope, message.name())) {
+                    continue;
+                }
+                // generate messages as schemas
+                gen.path.push(idx as i32);
+                eprintln!("generating message {}", message.name());
+                let schema = gen.generate_schema_recursive(message, 0)?;
+                for (name, schema) in schema {
+                    if imported {
+                        import_schemas.insert(name.clone());
+                    }
+                    if schema_map.insert(name.clone(), with_package(schema)).is_some() {
+                        return Err(Error::SchemaCollision(name));
+                    }
+                }
+                gen.path.pop();
+            }
+            gen.path.pop();
+
+            gen.path.push(5);
+            for (idx, enum_type) in file.enum_type.iter().enumerate() {
+                let full_name = format!("{}.{}", gen.scope, enum_type.name());
+                if !generated_types.insert(full_name.clone()) {
+                    continue;
+                }
+                gen.path.push(idx as i32);
+                eprintln!("generating enum {}", enum_type.name());
+                let schema = gen.generate_enum_schema(&enum_type.value);
+                let name = gen.schema_name(&full_name);
+                if imported {
+                    import_schemas.insert(name.clone());
+                }
+                if schema_map.insert(name.clone(), with_package(schema)).is_some() {
+                    return Err(Error::SchemaCollision(name));
+                }
+                gen.path.pop();
+            }
+            gen.path.pop();
+
+            gen.path.push(6);
+            for (idx, service) in file.service.into_iter().enumerate() {
+                // generate services as paths
+                gen.path.push(idx as i32);
+                eprintln!("generating service {}", service.name());
+                let svc = gen.generate_service(service)?;
+                let service_description = comment_description(&svc.comments.leading);
+                let service_deprecated = parse_annotations(&svc.comments.leading).contains_key("deprecated") || svc.options.deprecated();
+                let service_name = [svc.package.as_str(), &svc.name].iter().filter(|n| !n.is_empty()).join(".");
+
+                let method_infos = svc.methods.into_iter().enumerate()
+                    .map(|(method_idx, m)| {
+                        // the google.api.http option takes precedence over the routes in the comment
+                        let method_path = gen.path.iter().chain(&[2, method_idx as i32]).copied().collect_vec();
+                        let possible_paths = match http_rules.get(&method_path) {
+                            Some(rule) => gen.http_rule_paths(rule, &m.input_proto_type),
+                            None => m.comments.leading.iter().filter_map(|comment| OpenAPIPathInfo::try_from(comment).ok()).collect(),
+                        };
+                        let annotations = parse_annotations(&m.comments.leading);
+                        let (summary, description) = method_summary(&m.comments.leading);
+                        let method_callbacks = gen.generate_callbacks(&annotations)?;
+                        let method_info = OpenAPIMethodInfo {
+                            service_name: service_name.clone(),
+                            method_name: format!("{}.{}", service_name, m.name),
+                            input_type: m.input_proto_type,
+                            output_type: m.output_proto_type,
+                            annotations,
+                            file_stem: file_stem.clone(),
+                            service_description: service_description.clone(),
+                            service_deprecated,
+                            deprecated: m.options.deprecated(),
+                            summary,
+                            description,
+                            callbacks: method_callbacks.keys().cloned().collect(),
+                        };
+                        // callbacks of methods without an operation aren't referenced
+                        if !possible_paths.is_empty() {
+                            for (name, callback) in method_callbacks {
+                                add_callback(&mut callbacks, name, callback)?;
+                            }
+                        }
+                        Ok((method_info, possible_paths))
+                    }).collect::<Result<Vec<_>, Error>>()?;
+                // collect all possible unique paths, in the order of the methods so the output is stable
+                // routes only differing in the types of their parameters (like `{id:int}` and `{id:string}`) share a path
+                let mut paths = IndexMap::new();
+                for (method_info, possible_paths) in method_infos {
+                    for path in possible_paths {
+                        paths.entry(gen.openapi_path(&path.path))
+                            .or_insert_with(Vec::new)
+                            .push((method_info.clone(), path));
+                    }
+                }
+
+                for (path, path_info) in paths {
+                    eprintln!("generating path {}", path);
+                    let mut path_item = gen.generate_path(&path_info)?;
+                    let path = match (&version_prefix, path) {
+                        (Some(prefix), path) if path == "/" => prefix.clone(),
+                        (Some(prefix), path) => format!("{}{}", prefix, path),
+                        (None, path) => path,
+                    };
+                    let path = match path.as_str() {
+                        "/" if !path_prefix.is_empty() => path_prefix.clone(),
+                        _ => format!("{}{}", path_prefix, path),
+                    };
+                    // the parameters of the prefix come first, like in the path
+                    if !prefix_parameters.is_empty() {
+                        let (_, first) = &path_info[0];
+                        let mut parameters = prefix_parameters.iter()
+                            .filter(|(param, _)| {
+                                let duplicate = first.parameters.contains_key(param);
+                                if duplicate {
+                                    eprintln!("warning: the path prefix parameter {} is also a parameter of {}, using the latter", param, path);
+                                }
+                                !duplicate
+                            })
+                            .map(|(param, param_type)| path_parameter(param, param_type))
+                            .collect_vec();
+                        parameters.append(&mut path_item.parameters);
+                        path_item.parameters = parameters;
+                    }
+                    // other services (or files) may use the same path, the first operation of each method wins
+                    if let Some(ReferenceOr::Item(existing)) = openapi.paths.get(&path) {
+                        let taken = path_item_operations(existing).into_iter().map(|(method, _)| method).collect_vec();
+                        for (method, slot) in path_item_slots_mut(&mut path_item) {
+                            if slot.is_some() && taken.contains(&method) {
+                                eprintln!("warning: ignoring {} {}, because another method already uses it", method.to_uppercase(), path);
+                                *slot = None;
+                            }
+                        }
+                    }
+                    // methods with multiple routes (or services of the same name) would repeat an ID, which has to be unique,
+                    // just like the IDs of methods whose names only differ in case after the operation_id_case
+                    let numbered = |id: &str, n| match options.operation_id_case {
+                        Some(case) => case.numbered(id, n),
+                        None => options.operation_id_style.numbered(id, n),
+                    };
+                    for operation in path_item_operations_mut(&mut path_item) {
+                        if let Some(id) = operation.operation_id.take() {
+                            let unique = (1..).map(|n| if n == 1 { id.clone() } else { numbered(&id, n) })
+                                .find(|candidate| !operation_ids.contains(candidate))
+                                .unwrap();
+                            operation_ids.insert(unique.clone());
+                            operation.operation_id = Some(unique);
+                        }
+                    }
+                    // the first method of an HTTP method gets the operation, like in generate_path
+                    let operations = path_item_operations(&path_item).into_iter().map(|(method, _)| method).collect_vec();
+                    for (method_info, path_def) in path_info.iter().unique_by(|(_, path_def)| &path_def.method) {
+                        if operations.iter().any(|method| method.eq_ignore_ascii_case(&path_def.method)) {
+                            method_operations.entry(method_info.method_name.clone())
+                                .or_insert_with(Vec::new)
+                                .push(format!("{} {}", path_def.method, path));
+                        }
+                    }
+                    match openapi.paths.get_mut(&path) {
+                        Some(ReferenceOr::Item(existing)) => merge_path_item(existing, path_item),
+                        _ => {
+                            openapi.paths.insert(path, ReferenceOr::Item(path_item));
+                        }
+                    }
+                }
+                gen.path.pop();
+            }
+            gen.path.pop();
+        }
+        // shared responses aren't part of a file, so they are generated without source info
+        let gen = OpenAPIGenerator {
+            config,
+            options,
+            messages: &messages,
+            schema_names: &schema_names,
+            source_info: SourceCodeInfo::default(),
+            constraints: HashMap::new(),
+            field_behaviors: HashMap::new(),
+            scope: String::new(),
+            proto3: false,
+            path: Vec::new(),
+        };
+        if let Some(number) = options.example_option {
+            for (type_name, example) in extensions::collect_message_options(&raw_files, number) {
+                let schema = match schema_map.get_mut(&gen.schema_name(&type_name)) {
+                    Some(ReferenceOr::Item(schema)) => schema,
+                    _ => continue,
+                };
+                match serde_json::from_str(&example) {
+                    Ok(example) => schema.schema_data.example = Some(example),
+                    Err(err) => eprintln!("warning: ignoring the example of {}, because it is not valid JSON: {}", type_name.trim_start_matches('.'), err),
+                }
+            }
+        }
+        // the proto files may define google.rpc.Status themselves, then its schema is used as is
+        if options.grpc_error_model && !schema_names.contains_key(".google.rpc.Status") {
+            let name = gen.schema_name(".google.rpc.Status");
+            if schema_map.insert(name.clone(), ReferenceOr::Item(grpc_status_schema())).is_some() {
+                return Err(Error::SchemaCollision(name));
+            }
+        }
+        let responses = gen.generate_shared_responses(&schema_map);
+        // schemas are used if the operations or shared responses reference them, directly or through other schemas
+        let roots = serde_json::json!([&openapi.paths, &responses, &callbacks]);
+        if options.no_import_schemas {
+            // the schemas of imports referenced by the input files are still needed for the references to resolve
+            let input_schemas = schema_map.iter().filter(|(name, _)| !import_schemas.contains(*name)).collect::<IndexMap<_, _>>();
+            let used = used_schemas(&serde_json::json!([&roots, input_schemas]), &schema_map);
+            schema_map.retain(|name, _| !import_schemas.contains(name) || used.contains(name));
+        }
+        if options.omit_unused_schemas {
+            let used = used_schemas(&roots, &schema_map);
+            schema_map.retain(|name, _| used.contains(name));
+        }
+        match options.schema_sort {
+            SchemaSort::Declaration => {}
+            SchemaSort::Alpha => schema_map.sort_keys(),
+            SchemaSort::Usage => {
+                // unused schemas keep their declaration order after the used ones
+                let used = used_schemas(&roots, &schema_map);
+                let position = |name: &String| used.iter().position(|used| used == name).unwrap_or(used.len());
+                schema_map.sort_by(|a, _, b, _| position(a).cmp(&position(b)));
+            }
+        }
+        if options.group_schemas_by_package {
+            // packages are ordered by their first schema, the schemas of a package keep their order
+            let packages = schema_map.values().map(|schema| schema_package(schema).map(str::to_owned)).unique().collect_vec();
+            let position = |schema: &ReferenceOr<Schema>| packages.iter().position(|package| package.as_deref() == schema_package(schema));
+            schema_map.sort_by(|_, a, _, b| position(a).cmp(&position(b)));
+        }
+        if let Some(mapping_path) = &options.emit_mapping {
+            // schemas which were skipped or omitted have no location
+            let schema_locations = |names: Vec<&String>| names.into_iter()
+                .filter(|name| schema_map.contains_key(&gen.schema_name(name)))
+                .map(|name| (name.trim_start_matches('.').to_string(), format!("#/components/schemas/{}", gen.schema_name(name))))
+                .collect::<BTreeMap<_, _>>();
+            let message_names = messages.iter()
+                .filter(|(_, message)| !message.options.as_ref().is_some_and(|options| options.map_entry()))
+                .map(|(name, _)| name)
+                .collect();
+            let mapping = serde_json::json!({
+                "messages": schema_locations(message_names),
+                "enums": schema_locations(enum_names.iter().collect()),
+                "methods": method_operations,
+            });
+            let written = std::fs::File::create(mapping_path)
+                .map_err(Box::<dyn std::error::Error>::from)
+                .and_then(|file| serde_json::to_writer_pretty(file, &mapping).map_err(Into::into));
+            if let Err(err) = written {
+                eprintln!("warning: failed to write the mapping to {}: {}", mapping_path.display(), err);
+            }
+        }
+        let components = Components {
+            security_schemes: IndexMap::new(),
+            responses,
+            parameters: IndexMap::new(),
+            request_bodies: IndexMap::new(),
+            headers: IndexMap::new(),
             schemas: schema_map,
             examples: IndexMap::new(),
             links: IndexMap::new(),
-            callbacks: IndexMap::new(),
+            callbacks,
             extensions: IndexMap::new(),
+        };
+        // an empty components object would still be serialized as `components: {}`
+        openapi.components = Some(components).filter(|c| !components_are_empty(c));
+        // files only become documented tags if an operation is tagged with them
+        let used_tags = openapi.paths.values()
+            .filter_map(|path_item| match path_item {
+                ReferenceOr::Item(path_item) => Some(path_item),
+                ReferenceOr::Reference { .. } => None,
+            })
+            .flat_map(path_item_operations)
+            .flat_map(|(_, operation)| operation.tags.clone())
+            .collect_vec();
+        for (name, description) in file_tags {
+            if used_tags.contains(&name) && !openapi.tags.iter().any(|tag| tag.name == name) {
+                openapi.tags.push(Tag {
+                    name,
+                    description,
+                    external_docs: None,
+                    extensions: IndexMap::new(),
+                });
+            }
+        }
+        for tag in options.additional_tags.iter().unique() {
+            openapi.tags.push(Tag {
+                name: tag.clone(),
+                description: None,
+                external_docs: None,
+                extensions: IndexMap::new(),
+            });
+        }
+        if options.title_case_tags {
+            // every tag needs an entry to carry its display name, the name itself stays the same
+            for name in used_tags.iter().unique() {
+                if !openapi.tags.iter().any(|tag| &tag.name == name) {
+                    openapi.tags.push(Tag {
+                        name: name.clone(),
+                        description: None,
+                        external_docs: None,
+                        extensions: IndexMap::new(),
+                    });
+                }
+            }
+            for tag in &mut openapi.tags {
+                tag.extensions.insert("x-displayName".to_string(), title_case(&tag.name).into());
+            }
+        }
+        openapi.servers = options.servers.iter().map(|server| generate_server(server, &options.server_variables)).collect();
+        openapi.openapi = "3.0.0".to_string();
+        if options.emit_index {
+            let index = generate_index(&openapi);
+            openapi.extensions.insert("x-api-index".to_string(), index);
+        }
+
+        Ok(openapi)
+    }
+
+    /// Prints the services and methods of the proto files along with the routes found in their comments, without generating anything.
+    pub fn list(
+        config: &mut Config,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+        options: &GeneratorOptions,
+    ) -> Result<(), Error> {
+        let raw_files = Self::raw_descriptor(config, protos, includes, options)?;
+        let files = FileDescriptorSet::decode(&*raw_files)?.file;
+        let messages = collect_messages(&files);
+        let mut file_http_rules = extensions::collect_http_rules(&raw_files).into_iter();
+
+        for file in files {
+            let http_rules = file_http_rules.next().unwrap_or_default();
+            let mut gen = OpenAPIGenerator {
+                config,
+                options,
+                messages: &messages,
+                schema_names: &HashMap::new(),
+                source_info: sorted_source_info(&file),
+                constraints: HashMap::new(),
+                field_behaviors: HashMap::new(),
+                scope: if file.package().is_empty() { String::new() } else { format!(".{}", file.package()) },
+                proto3: file.syntax() == "proto3",
+                path: vec![6],
+            };
+
+            for (idx, service) in file.service.into_iter().enumerate() {
+                gen.path.push(idx as i32);
+                let svc = gen.generate_service(service)?;
+                if svc.package.is_empty() {
+                    println!("{}", svc.name);
+                } else {
+                    println!("{}.{}", svc.package, svc.name);
+                }
+                for (method_idx, method) in svc.methods.into_iter().enumerate() {
+                    let path_defs = match http_rules.get(&[6, idx as i32, 2, method_idx as i32][..]) {
+                        Some(rule) => gen.http_rule_paths(rule, &method.input_proto_type),
+                        None => method.comments.leading.iter().filter_map(|comment| OpenAPIPathInfo::try_from(comment).ok()).collect(),
+                    };
+                    let routes = path_defs.iter()
+                        .map(|path_def| format!("{} {}", path_def.method, gen.openapi_path(&path_def.path)))
+                        .collect_vec();
+                    if routes.is_empty() {
+                        println!("  {}: (no route)", method.name);
+                    } else {
+                        println!("  {}: {}", method.name, routes.join(", "));
+                    }
+                }
+                gen.path.pop();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the elements of the proto files which have no description, for the `require_descriptions` option.
+    ///
+    /// Only the given proto files are checked, not their imports. Without proto files (using `descriptor_sets`),
+    /// every file is checked.
+    pub fn undocumented(
+        config: &mut Config,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+        options: &GeneratorOptions,
+    ) -> Result<Vec<String>, Error> {
+        let raw_files = Self::raw_descriptor(config, protos, includes, options)?;
+        let files = FileDescriptorSet::decode(&*raw_files)?.file;
+        let messages = collect_messages(&files);
+        let kinds = &options.require_descriptions;
+        let mut file_http_rules = extensions::collect_http_rules(&raw_files).into_iter();
+
+        let mut undocumented = Vec::new();
+        for file in files {
+            let http_rules = file_http_rules.next().unwrap_or_default();
+            if !protos.is_empty() && !protos.iter().any(|proto| proto.as_ref().ends_with(file.name())) {
+                continue;
+            }
+            let mut gen = OpenAPIGenerator {
+                config,
+                options,
+                messages: &messages,
+                schema_names: &HashMap::new(),
+                source_info: sorted_source_info(&file),
+                constraints: HashMap::new(),
+                field_behaviors: HashMap::new(),
+                scope: String::new(),
+                proto3: file.syntax() == "proto3",
+                path: Vec::new(),
+            };
+
+            gen.path.push(4);
+            gen.undocumented_messages(&file.message_type, file.package(), file.name(), &mut undocumented);
+            gen.path.pop();
+
+            if kinds.contains(&DescriptionKind::EnumValues) {
+                gen.path.push(5);
+                for (idx, enum_type) in file.enum_type.iter().enumerate() {
+                    gen.path.push(idx as i32);
+                    let name = [file.package(), enum_type.name()].iter().filter(|n| !n.is_empty()).join(".");
+                    gen.undocumented_enum_values(&enum_type.value, &name, file.name(), &mut undocumented);
+                    gen.path.pop();
+                }
+                gen.path.pop();
+            }
+
+            if kinds.contains(&DescriptionKind::Operations) {
+                gen.path.push(6);
+                for (idx, service) in file.service.iter().enumerate() {
+                    gen.path.push(idx as i32);
+                    gen.path.push(2);
+                    for (method_idx, method) in service.method.iter().enumerate() {
+                        gen.path.push(method_idx as i32);
+                        // methods without a route don't become operations
+                        let comments = Comments::from_location(gen.location());
+                        if http_rules.contains_key(&gen.path) || comments.leading.iter().any(|line| OpenAPIPathInfo::try_from(line).is_ok()) {
+                            let name = [file.package(), service.name(), method.name()].iter().filter(|n| !n.is_empty()).join(".");
+                            gen.check_description("operation", &name, file.name(), &mut undocumented);
+                        }
+                        gen.path.pop();
+                    }
+                    gen.path.pop();
+                    gen.path.pop();
+                }
+                gen.path.pop();
+            }
+        }
+
+        Ok(undocumented)
+    }
+
+    /// Adds the messages (including nested ones), fields and enum values without a description to `undocumented`,
+    /// depending on the `require_descriptions` option. The current path needs to point to the list of messages.
+    fn undocumented_messages(&mut self, messages: &[DescriptorProto], scope: &str, file: &str, undocumented: &mut Vec<String>) {
+        let kinds = &self.options.require_descriptions;
+        for (idx, message) in messages.iter().enumerate() {
+            // map entries are generated by protoc
+            if message.options.as_ref().is_some_and(|options| options.map_entry()) {
+                continue;
+            }
+            let name = [scope, message.name()].iter().filter(|n| !n.is_empty()).join(".");
+            self.path.push(idx as i32);
+            if kinds.contains(&DescriptionKind::Messages) {
+                self.check_description("message", &name, file, undocumented);
+            }
+            if kinds.contains(&DescriptionKind::Fields) {
+                self.path.push(2);
+                for (field_idx, field) in message.field.iter().enumerate() {
+                    self.path.push(field_idx as i32);
+                    self.check_description("field", &format!("{}.{}", name, field.name()), file, undocumented);
+                    self.path.pop();
+                }
+                self.path.pop();
+            }
+            if kinds.contains(&DescriptionKind::EnumValues) {
+                self.path.push(4);
+                for (enum_idx, enum_type) in message.enum_type.iter().enumerate() {
+                    self.path.push(enum_idx as i32);
+                    self.undocumented_enum_values(&enum_type.value, &format!("{}.{}", name, enum_type.name()), file, undocumented);
+                    self.path.pop();
+                }
+                self.path.pop();
+            }
+            self.path.push(3);
+            self.undocumented_messages(&message.nested_type, &name, file, undocumented);
+            self.path.pop();
+            self.path.pop();
+        }
+    }
+
+    /// Adds the values of the enum at the current path without a description to `undocumented`.
+    fn undocumented_enum_values(&mut self, values: &[EnumValueDescriptorProto], enum_name: &str, file: &str, undocumented: &mut Vec<String>) {
+        self.path.push(2);
+        for (idx, value) in values.iter().enumerate() {
+            self.path.push(idx as i32);
+            self.check_description("enum value", &format!("{}.{}", enum_name, value.name()), file, undocumented);
+            self.path.pop();
+        }
+        self.path.pop();
+    }
+
+    /// Adds the element at the current path to `undocumented`, if neither its leading nor its trailing comment
+    /// contains a description. Annotations and routes are not descriptions.
+    fn check_description(&self, kind: &str, name: &str, file: &str, undocumented: &mut Vec<String>) {
+        let location = self.location();
+        let comments = Comments::from_location(location);
+        let described = comments.leading.iter().chain(&comments.trailing).any(|line| {
+            !line.trim().is_empty() && !ANNOTATION_RE.is_match(line) && OpenAPIPathInfo::try_from(line).is_err()
         });
-        openapi.openapi = "3.0.0".to_string();
+        if !described {
+            // spans are zero-based
+            let line = location.span.first().map_or(0, |line| line + 1);
+            undocumented.push(format!("{}:{}: {} {} has no description", file, line, kind, name));
+        }
+    }
+
+    /// Generates an OpenAPI object and serializes it to a writer in the given format.
+    pub fn generate_to_writer(
+        config: &mut Config,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+        options: &GeneratorOptions,
+        format: OutputFormat,
+        writer: impl Write,
+    ) -> Result<(), Error> {
+        let openapi = OpenAPIGenerator::generate(config, protos, includes, options)?;
+        write_openapi(&openapi, format, writer).map_err(Error::Write)
+    }
+
+    /// Converts a path from a proto comment to the key in the OpenAPI document, honoring the `strip_path_prefix` option.
+    pub fn openapi_path(&self, path: &str) -> String {
+        let path = path_to_openapi_path(path);
+        let prefix = match &self.options.strip_path_prefix {
+            Some(prefix) => format!("/{}", prefix.trim_matches('/')),
+            None => return path,
+        };
 
-        openapi
+        // only strip whole segments, so /api doesn't turn /apiary into /ary
+        match path.strip_prefix(&prefix) {
+            Some("") => "/".to_string(),
+            Some(rest) if rest.starts_with('/') => rest.to_string(),
+            _ => path,
+        }
     }
 
     /// Generate an OpenAPI path item from a set of path definitions.
-    pub fn generate_path(&self, path_info: &[(String, String, OpenAPIPathInfo)]) -> openapiv3::PathItem {
+    pub fn generate_path(&self, path_info: &[(OpenAPIMethodInfo, OpenAPIPathInfo)]) -> Result<openapiv3::PathItem, Error> {
         let mut path_item = openapiv3::PathItem::default();
 
-        // fill in parameters, if present
+        // path parameters are shared by every operation of the path, so they belong to the path item,
+        // while all other parameters can differ between the operations
         // since the path definitions are grouped before being passed to this function,
         // we can assume that the parameters are in the same order as the path and same for every path definition
-        let (_, _, first) = path_info.first().unwrap();
-        if !first.parameters.is_empty() {
-            for (param, param_type) in &first.parameters {
-                path_item.parameters.push(ReferenceOr::Item(Parameter::Path {
-                    style: PathStyle::Simple,
-                    parameter_data: ParameterData {
-                        name: param.clone(),
-                        description: None,
-                        required: true,
-                        deprecated: None,
-                        format: ParameterSchemaOrContent::Schema(ReferenceOr::Item(Schema {
-                            schema_data: SchemaData::default(),
-                            schema_kind: SchemaKind::Type(match param_type.as_str() {
-                                "string" => Type::String(StringType::default()),
-                                "int" => Type::Integer(IntegerType::default()),
-                                _ => Type::String(StringType::default()),
-                            }),
-                        })),
-                        example: None,
-                        examples: IndexMap::new(),
-                        explode: None,
-                        extensions: IndexMap::new(),
-                    }
-                }));
+        let (_, first) = path_info.first().ok_or(Error::EmptyPath)?;
+        path_item.parameters = self.generate_path_parameters(first);
+
+        // the first method with an annotation wins, since the path item is shared
+        let annotation = |name| path_info.iter().find_map(|(method_info, _)| method_info.annotations.get(name).cloned());
+        path_item.summary = annotation("path-summary");
+        path_item.description = annotation("path-description").or_else(|| {
+            if self.options.path_description_from_service {
+                path_info.iter().find_map(|(method_info, _)| method_info.service_description.clone())
+            } else {
+                None
             }
-        }
+        });
+        // path items can't be deprecated themselves, so all of their operations are
+        let path_deprecated = annotation("path-deprecated").is_some();
 
-        for (input_type, output_type, path_def) in path_info {
+        for (method_info, path_def) in path_info {
+            let input_type = &method_info.input_type;
+            let output_type = &method_info.output_type;
+            let has_body = path_def.include_body && (path_def.method != *"GET" || (self.options.allow_get_body && path_def.explicit_body));
+            let examples = self.generate_examples(method_info);
+            if !has_body && !examples.is_empty() {
+                eprintln!("warning: ignoring @example of {} {}, because it has no request body", path_def.method, path_def.path);
+            }
+            let body_field_schema = match &path_def.body_field {
+                Some(field_name) => {
+                    let schema = self.body_field_schema(input_type, field_name)?;
+                    if schema.is_none() {
+                        eprintln!("warning: the body field {} of {} {} does not exist, the whole message is used", field_name, path_def.method, path_def.path);
+                    }
+                    schema
+                }
+                None => None,
+            };
+            let body_schema = body_field_schema.unwrap_or_else(|| self.type_ref(input_type));
             let mut body_map = IndexMap::new();
             body_map.insert(
                 "application/json".to_string(),
                 MediaType {
-                    schema: Some(ReferenceOr::ref_(format!("#/components/schemas/{}", input_type.split('.').last().unwrap()).as_str())),
+                    schema: Some(body_schema),
+                    examples,
                     ..MediaType::default()
                 }
             );
 
+            let mut response_schema = self.type_ref(output_type);
+            if method_info.annotations.contains_key("nullable-response") {
+                // nullable next to a reference is ignored, so the reference is wrapped
+                response_schema = ReferenceOr::Item(Schema {
+                    schema_data: SchemaData { nullable: true, ..SchemaData::default() },
+                    schema_kind: SchemaKind::AllOf { all_of: vec![response_schema] },
+                });
+            }
             let mut response_map = IndexMap::new();
             response_map.insert(
                 "application/json".to_string(),
                 MediaType {
-                    schema: Some(ReferenceOr::ref_(format!("#/components/schemas/{}", output_type.split('.').last().unwrap()).as_str())),
+                    schema: Some(response_schema),
                     ..MediaType::default()
                 }
             );
+            let success_response = Response {
+                content: response_map,
+                description: format!("A response containing {}", output_type.rsplit('.').next().unwrap()),
+                ..Response::default()
+            };
+            // the status of the route is more specific than the one of the method
+            let status = match path_def.success_status {
+                Some(code) if (100..=599).contains(&code) => code,
+                Some(code) => {
+                    eprintln!("warning: ignoring 'STATUS {}' of {} {}, because it is not a valid HTTP status code", code, path_def.method, path_def.path);
+                    self.success_status(method_info)
+                }
+                None => self.success_status(method_info),
+            };
             let mut responses = IndexMap::new();
-            responses.insert(
-                StatusCode::Code(200_u16),
-                ReferenceOr::Item(Response {
-                    content: response_map,
-                    description: format!("A response containing {}", output_type.split('.').last().unwrap()),
+            if status != 200 && !self.options.replace_default_status.unwrap_or(true) {
+                responses.insert(StatusCode::Code(200), ReferenceOr::Item(success_response.clone()));
+            }
+            let mut success_response = success_response;
+            if status == 204 {
+                // a 204 response has no content by definition
+                success_response.content.clear();
+                success_response.description = "No content".to_string();
+            }
+            responses.insert(StatusCode::Code(status), ReferenceOr::Item(success_response));
+
+            for codes in method_info.annotations.get_vec("responses").into_iter().flatten() {
+                for code in codes.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+                    match code.parse::<u16>() {
+                        Ok(code) if (100..=599).contains(&code) && self.options.responses.contains_key(&code) => {
+                            responses.insert(
+                                StatusCode::Code(code),
+                                ReferenceOr::ref_(&self.component_ref("responses", &code.to_string())),
+                            );
+                        }
+                        _ => eprintln!("warning: ignoring '@responses {}', because there is no shared response for it", code),
+                    }
+                }
+            }
+            // the responses of the route are the most specific, so they replace the other ones with the same status
+            for (code, message) in &path_def.errors {
+                if !(100..=599).contains(code) {
+                    eprintln!("warning: ignoring the response {}={} of {} {}, because it is not a valid HTTP status code", code, message, path_def.method, path_def.path);
+                    continue;
+                }
+                let mut content = IndexMap::new();
+                content.insert("application/json".to_string(), MediaType {
+                    schema: Some(self.type_ref(message)),
+                    ..MediaType::default()
+                });
+                responses.insert(StatusCode::Code(*code), ReferenceOr::Item(Response {
+                    content,
+                    description: format!("A response containing {}", message.rsplit('.').next().unwrap()),
                     ..Response::default()
-                })
-            );
+                }));
+            }
 
-            let operation = openapiv3::Operation {
-                request_body: if path_def.method != *"GET" && path_def.include_body {
+            let mut operation = openapiv3::Operation {
+                parameters: self.generate_operation_parameters(method_info, path_def)?,
+                request_body: if has_body {
                     Some(ReferenceOr::Item(RequestBody {
                         content: body_map,
                         ..RequestBody::default()
@@ -323,31 +2248,494 @@ impl<'a> OpenAPIGenerator<'a> {
                     None
                 },
                 responses: Responses {
-                    default: None,
+                    default: self.grpc_error_response().map(ReferenceOr::Item),
                     responses,
                 },
-                tags: path_def.tags.clone(),
+                tags: self.operation_tags(method_info, path_def),
+                summary: method_info.summary.clone(),
+                description: method_info.description.clone(),
+                deprecated: path_deprecated || method_info.service_deprecated || method_info.deprecated || method_info.annotations.contains_key("deprecated"),
                 ..Operation::default()
             };
+            // the proto names allow mapping an operation back to its method
+            let method_name = method_info.method_name.rsplit('.').next().unwrap_or_default();
+            let service_name = method_info.service_name.rsplit('.').next().unwrap_or_default();
+            let operation_id = self.options.operation_id_style.operation_id(service_name, method_name);
+            operation.operation_id = Some(match self.options.operation_id_case {
+                Some(case) => case.apply(&operation_id),
+                None => operation_id,
+            });
+            operation.extensions.insert("x-proto-service".to_string(), method_info.service_name.clone().into());
+            operation.extensions.insert("x-proto-method".to_string(), method_name.into());
+            if !method_info.callbacks.is_empty() {
+                // the operation of this openapiv3 version has no callbacks, so they are added as an extension
+                // referencing the callbacks in the components
+                let refs: serde_json::Map<String, serde_json::Value> = method_info.callbacks.iter()
+                    .map(|name| (name.clone(), serde_json::json!({ "$ref": self.component_ref("callbacks", name) })))
+                    .collect();
+                operation.extensions.insert("callbacks".to_string(), serde_json::Value::Object(refs));
+            }
 
-            match path_def.method.as_str() {
-                "GET" => {
-                    path_item.get = Some(operation);
-                },
-                "POST" => {
-                    path_item.post = Some(operation);
+            let slot = match operation_slot(&mut path_item, &path_def.method) {
+                Some(slot) => slot,
+                None => {
+                    eprintln!("warning: ignoring {} {}, because OpenAPI doesn't support the method", path_def.method, path_def.path);
+                    continue;
+                }
+            };
+            // methods are in the order of the proto file, so the first one consistently wins
+            if slot.is_some() {
+                eprintln!("warning: ignoring {} {}, because another method already uses it", path_def.method, path_def.path);
+                continue;
+            }
+            *slot = Some(operation);
+        }
+
+        Ok(path_item)
+    }
+
+    /// Converts the `google.api.http` option of a method to its path definitions, one per binding.
+    ///
+    /// Path parameters are typed like the field of the input message they refer to. Templates matching multiple
+    /// segments, like `{name=shelves/*}`, can't be expressed in OpenAPI and become a plain parameter.
+    pub fn http_rule_paths(&self, rule: &HttpRule, input_type: &str) -> Vec<OpenAPIPathInfo> {
+        let mut path_defs = Vec::new();
+        for binding in std::iter::once(rule).chain(&rule.additional_bindings) {
+            let (method, template) = match binding.pattern() {
+                Some(pattern) => pattern,
+                None => {
+                    eprintln!("warning: ignoring a google.api.http binding of {}, because it has no pattern", input_type);
+                    continue;
+                }
+            };
+            let parameters = HTTP_PARAM_RE.captures_iter(template)
+                .map(|c| (c["param"].to_string(), self.path_parameter_type(input_type, &c["param"]).to_string()))
+                .collect();
+            // the body is either the whole message (`*`), a single field or missing
+            let body = binding.body.as_deref().filter(|body| !body.is_empty());
+
+            path_defs.push(OpenAPIPathInfo {
+                path: HTTP_PARAM_RE.replace_all(template, "{$param}").into_owned(),
+                method,
+                parameters,
+                include_body: body.is_some(),
+                explicit_body: true,
+                body_field: body.filter(|body| *body != "*").map(str::to_owned),
+                tags: Vec::new(),
+                errors: Vec::new(),
+                success_status: None,
+                query: Vec::new(),
+            });
+        }
+
+        path_defs
+    }
+
+    /// Returns the type of a path parameter, like `int64` or `string`, by following its (possibly nested) field path
+    /// like `book.id` through the given message.
+    fn path_parameter_type(&self, type_name: &str, param: &str) -> &'static str {
+        let mut message = self.messages.get(type_name);
+        let mut field = None;
+        for segment in param.split('.') {
+            field = message.and_then(|message| message.field.iter().find(|field| field.name() == segment));
+            message = field.and_then(|field| self.messages.get(field.type_name()));
+        }
+
+        match field.map(FieldDescriptorProto::r#type) {
+            Some(field_descriptor_proto::Type::Int32)
+            | Some(field_descriptor_proto::Type::Sint32)
+            | Some(field_descriptor_proto::Type::Sfixed32) => "int32",
+            Some(field_descriptor_proto::Type::Int64)
+            | Some(field_descriptor_proto::Type::Sint64)
+            | Some(field_descriptor_proto::Type::Sfixed64) => "int64",
+            Some(field_descriptor_proto::Type::Uint32)
+            | Some(field_descriptor_proto::Type::Fixed32) => "uint32",
+            Some(field_descriptor_proto::Type::Uint64)
+            | Some(field_descriptor_proto::Type::Fixed64) => "uint64",
+            Some(field_descriptor_proto::Type::Bool) => "bool",
+            Some(field_descriptor_proto::Type::Float) => "float",
+            Some(field_descriptor_proto::Type::Double) => "double",
+            _ => "string",
+        }
+    }
+
+    /// Returns the schema of the field of a message which is selected as the request body, like `body: "book"`
+    /// of a `google.api.http` option.
+    ///
+    /// Returns `None` if the message has no such field.
+    fn body_field_schema(&self, type_name: &str, field_name: &str) -> Result<Option<ReferenceOr<Schema>>, Error> {
+        let field = match self.messages.get(type_name).and_then(|message| message.field.iter().find(|field| field.name() == field_name)) {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        let schema = match &field.type_name {
+            Some(type_name) => self.type_schema(type_name),
+            None => ReferenceOr::Item(Schema {
+                schema_data: SchemaData::default(),
+                schema_kind: SchemaKind::Type(scalar_type_to_schema(field, self.options)?),
+            }),
+        };
+        if field.label() != Label::Repeated {
+            return Ok(Some(schema));
+        }
+
+        Ok(Some(ReferenceOr::Item(Schema {
+            schema_data: SchemaData::default(),
+            schema_kind: SchemaKind::Type(Type::Array(ArrayType {
+                min_items: None,
+                max_items: None,
+                unique_items: false,
+                items: match schema {
+                    ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
+                    ReferenceOr::Item(item) => ReferenceOr::boxed_item(item),
                 },
-                "PUT" => {
-                    path_item.put = Some(operation);
+            })),
+        })))
+    }
+
+    /// Generates the callbacks of the `@callback name METHOD {$request.body#/url} Message` annotations of a method,
+    /// keyed by their name. The callback request has the given message as its body if the method takes one (`POST`,
+    /// `PUT` and `PATCH`), otherwise its fields become query parameters.
+    ///
+    /// Invalid annotations are skipped with a warning. Fails if two different callbacks have the same name.
+    pub fn generate_callbacks(&self, annotations: &MultiMap<String, String>) -> Result<IndexMap<String, Callback>, Error> {
+        let mut callbacks = IndexMap::new();
+        for callback in annotations.get_vec("callback").into_iter().flatten() {
+            let (name, method, url, payload) = match callback.split_whitespace().collect_tuple() {
+                Some(parts) => parts,
+                None => {
+                    eprintln!("warning: ignoring invalid annotation '@callback {}', expected '@callback name METHOD url Message'", callback);
+                    continue;
+                }
+            };
+            let mut responses = IndexMap::new();
+            responses.insert(StatusCode::Code(200), ReferenceOr::Item(Response {
+                description: "The callback was received".to_string(),
+                ..Response::default()
+            }));
+            let mut operation = Operation {
+                responses: Responses { default: None, responses },
+                ..Operation::default()
+            };
+            // like the routes, methods without a body get the fields of the payload as query parameters
+            if matches!(method, "POST" | "PUT" | "PATCH") {
+                let mut content = IndexMap::new();
+                content.insert("application/json".to_string(), MediaType {
+                    schema: Some(self.type_ref(payload)),
+                    ..MediaType::default()
+                });
+                operation.request_body = Some(ReferenceOr::Item(RequestBody {
+                    content,
+                    required: true,
+                    ..RequestBody::default()
+                }));
+            } else {
+                let payload = self.resolve_type_name(payload);
+                operation.parameters = self.generate_query_parameters(&payload, &IndexMap::new())?;
+            }
+
+            let mut path_item = PathItem::default();
+            match operation_slot(&mut path_item, method) {
+                Some(slot) => *slot = Some(operation),
+                None => {
+                    eprintln!("warning: ignoring callback {}, because OpenAPI doesn't support the method {}", name, method);
+                    continue;
+                }
+            }
+            let mut expressions = IndexMap::new();
+            expressions.insert(url.to_string(), path_item);
+            if callbacks.get(name).is_some_and(|existing| *existing != expressions) {
+                return Err(Error::CallbackCollision(name.to_string()));
+            }
+            callbacks.insert(name.to_string(), expressions);
+        }
+
+        Ok(callbacks)
+    }
+
+    /// Generates the named request body examples of the `@example name {...}` annotations of a method.
+    ///
+    /// Examples whose value isn't valid JSON are skipped with a warning.
+    pub fn generate_examples(&self, method_info: &OpenAPIMethodInfo) -> IndexMap<String, ReferenceOr<Example>> {
+        let mut examples = IndexMap::new();
+        for example in method_info.annotations.get_vec("example").into_iter().flatten() {
+            let (name, value) = match example.split_once(char::is_whitespace) {
+                Some((name, value)) => (name, value.trim()),
+                None => {
+                    eprintln!("warning: ignoring invalid annotation '@example {}', expected '@example name {{...}}'", example);
+                    continue;
+                }
+            };
+            match serde_json::from_str(value) {
+                Ok(value) => {
+                    examples.insert(name.to_string(), ReferenceOr::Item(Example {
+                        value: Some(value),
+                        ..Example::default()
+                    }));
+                }
+                Err(err) => eprintln!("warning: ignoring example {}, because it is not valid JSON: {}", name, err),
+            }
+        }
+
+        examples
+    }
+
+    /// Returns the status code of the successful response of a method.
+    ///
+    /// A `@status` annotation takes precedence over the `status_from_response_name` suffixes, the default is 200.
+    pub fn success_status(&self, method_info: &OpenAPIMethodInfo) -> u16 {
+        if let Some(status) = method_info.annotations.get("status") {
+            match status.parse::<u16>() {
+                Ok(code) if (100..=599).contains(&code) => return code,
+                _ => eprintln!("warning: ignoring '@status {}', because it is not a valid HTTP status code", status),
+            }
+        }
+
+        // the longest suffix wins, so `CreatedResponse` can be mapped differently than `Response`
+        let name = method_info.output_type.rsplit('.').next().unwrap_or_default();
+        self.options.status_from_response_name.iter()
+            .filter(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .max_by_key(|(suffix, _)| suffix.len())
+            .map_or(200, |(_, code)| *code)
+    }
+
+    /// Generates the parameters of a path, which are shared by all of its operations.
+    pub fn generate_path_parameters(&self, path_def: &OpenAPIPathInfo) -> Vec<ReferenceOr<Parameter>> {
+        path_def.parameters.iter().map(|(param, param_type)| path_parameter(param, param_type)).collect()
+    }
+
+    /// Generates the parameters of a single operation, which are all parameters except the path parameters.
+    ///
+    /// Parameters named like a path parameter are skipped, since they would be ambiguous.
+    pub fn generate_operation_parameters(&self, method_info: &OpenAPIMethodInfo, path_def: &OpenAPIPathInfo) -> Result<Vec<ReferenceOr<Parameter>>, Error> {
+        let mut parameters = if !path_def.query.is_empty() {
+            // the fields listed in the route are used instead of all of them
+            let mut parameters = self.generate_query_parameters(&method_info.input_type, &path_def.parameters)?;
+            let name = |parameter: &ReferenceOr<Parameter>| match parameter {
+                ReferenceOr::Item(Parameter::Query { parameter_data, .. }) => Some(parameter_data.name.clone()),
+                _ => None,
+            };
+            for field in &path_def.query {
+                if path_def.parameters.contains_key(field) {
+                    eprintln!("warning: ignoring {} in QUERY of {} {}, because it is a path parameter", field, path_def.method, path_def.path);
+                } else if !parameters.iter().any(|parameter| name(parameter).as_ref() == Some(field)) {
+                    eprintln!("warning: ignoring {} in QUERY of {} {}, because it is not a field of {}", field, path_def.method, path_def.path, method_info.input_type.trim_start_matches('.'));
+                }
+            }
+            parameters.retain(|parameter| name(parameter).is_some_and(|name| path_def.query.contains(&name)));
+            parameters
+        } else if path_def.method == "GET" && self.options.query_params {
+            self.generate_query_parameters(&method_info.input_type, &path_def.parameters)?
+        } else {
+            Vec::new()
+        };
+        for param_json in method_info.annotations.get_vec("param-json").into_iter().flatten() {
+            match PARAM_JSON_RE.captures(param_json) {
+                Some(c) if path_def.parameters.contains_key(&c["param"]) => {
+                    eprintln!("warning: ignoring '@param-json {}', because {} is a path parameter", param_json, &c["param"]);
+                }
+                Some(c) => parameters.push(self.generate_json_parameter(&c["param"], &c["message"])),
+                None => eprintln!("warning: ignoring invalid annotation '@param-json {}', expected '@param-json name:Message'", param_json),
+            }
+        }
+
+        Ok(parameters)
+    }
+
+    /// Returns the default response of the `grpc_error_model` option, which contains a `google.rpc.Status`.
+    pub fn grpc_error_response(&self) -> Option<Response> {
+        if !self.options.grpc_error_model {
+            return None;
+        }
+        let mut content = IndexMap::new();
+        content.insert("application/json".to_string(), MediaType {
+            schema: Some(ReferenceOr::ref_(&self.schema_ref(".google.rpc.Status"))),
+            ..MediaType::default()
+        });
+
+        Some(Response {
+            description: "An error returned by the gRPC service".to_string(),
+            content,
+            ..Response::default()
+        })
+    }
+
+    /// Generates the shared responses of the `responses` option, skipping invalid status codes.
+    ///
+    /// The schema of a response is only referenced if it is one of the given schemas.
+    pub fn generate_shared_responses(&self, schemas: &IndexMap<String, ReferenceOr<Schema>>) -> IndexMap<String, ReferenceOr<Response>> {
+        let mut responses = IndexMap::new();
+        for (code, response) in &self.options.responses {
+            if !(100..=599).contains(code) {
+                eprintln!("warning: ignoring shared response {}, because it is not a valid HTTP status code", code);
+                continue;
+            }
+            let mut content = IndexMap::new();
+            if let Some(schema) = &response.schema {
+                if schemas.contains_key(&self.schema_name(schema)) {
+                    let content_type = response.content_type.as_deref().unwrap_or("application/json");
+                    content.insert(content_type.to_string(), MediaType {
+                        schema: Some(ReferenceOr::ref_(&self.schema_ref(schema))),
+                        ..MediaType::default()
+                    });
+                } else {
+                    eprintln!("warning: the schema {} of shared response {} does not exist, the response has no content", schema, code);
+                }
+            }
+            responses.insert(code.to_string(), ReferenceOr::Item(Response {
+                description: response.description.clone(),
+                content,
+                ..Response::default()
+            }));
+        }
+
+        responses
+    }
+
+    /// Returns the tags of an operation.
+    ///
+    /// The explicit tags of the path comment take precedence over the tag derived from the file (`tag_by_file`),
+    /// which takes precedence over the tag derived from the path (`tag_by_path`). The `additional_tags` are always added.
+    pub fn operation_tags(&self, method_info: &OpenAPIMethodInfo, path_def: &OpenAPIPathInfo) -> Vec<String> {
+        let mut tags = path_def.tags.clone();
+        if tags.is_empty() && self.options.tag_by_file && !method_info.file_stem.is_empty() {
+            tags.push(method_info.file_stem.clone());
+        }
+        if tags.is_empty() && self.options.tag_by_path {
+            let path = self.openapi_path(&path_def.path);
+            if let Some(segment) = path.split('/').find(|s| !s.is_empty() && !s.starts_with('{')) {
+                tags.push(segment.to_string());
+            }
+        }
+
+        tags.iter().chain(&self.options.additional_tags).unique().cloned().collect()
+    }
+
+    /// Generates a query parameter whose value is a JSON encoded message.
+    pub fn generate_json_parameter(&self, name: &str, type_name: &str) -> ReferenceOr<Parameter> {
+        let mut content = IndexMap::new();
+        content.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(self.type_ref(type_name)),
+                ..MediaType::default()
+            }
+        );
+
+        ReferenceOr::Item(Parameter::Query {
+            parameter_data: ParameterData {
+                name: name.to_string(),
+                description: None,
+                required: false,
+                deprecated: None,
+                format: ParameterSchemaOrContent::Content(content),
+                example: None,
+                examples: IndexMap::new(),
+                explode: None,
+                extensions: IndexMap::new(),
+            },
+            allow_reserved: false,
+            style: QueryStyle::Form,
+            allow_empty_value: None,
+        })
+    }
+
+    /// Flattens the fields of a message into query parameters.
+    ///
+    /// Fields of nested messages are named using dots (like `filter.name`), up to `query_depth` levels deep.
+    /// Fields which are already bound as path parameters are skipped.
+    pub fn generate_query_parameters(&self, type_name: &str, path_parameters: &IndexMap<String, String>) -> Result<Vec<ReferenceOr<Parameter>>, Error> {
+        let mut parameters = Vec::new();
+        let mut visited = Vec::new();
+        self.collect_query_parameters(type_name, "", 1, &mut visited, path_parameters, &mut parameters)?;
+
+        Ok(parameters)
+    }
+
+    fn collect_query_parameters(
+        &self,
+        type_name: &str,
+        prefix: &str,
+        depth: usize,
+        visited: &mut Vec<String>,
+        path_parameters: &IndexMap<String, String>,
+        parameters: &mut Vec<ReferenceOr<Parameter>>,
+    ) -> Result<(), Error> {
+        let message = match self.messages.get(type_name) {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+        // guard against recursive messages
+        if visited.iter().any(|v| v == type_name) {
+            return Ok(());
+        }
+        visited.push(type_name.to_string());
+
+        for field in &message.field {
+            let name = format!("{}{}", prefix, self.property_name(field));
+            if path_parameters.contains_key(&name) {
+                continue;
+            }
+            let repeated = field.label() == Label::Repeated;
+
+            let schema = match field.r#type() {
+                field_descriptor_proto::Type::Message => match well_known_type_schema(field.type_name(), self.options) {
+                    // well-known types like timestamps are plain values in JSON, while structs can't be flattened
+                    Some(schema @ Schema { schema_kind: SchemaKind::Type(Type::String(_) | Type::Integer(_) | Type::Number(_) | Type::Boolean {}), .. }) => {
+                        ReferenceOr::Item(schema)
+                    }
+                    Some(_) => continue,
+                    None => {
+                        if !repeated && depth < self.options.query_depth.unwrap_or(DEFAULT_QUERY_DEPTH) {
+                            self.collect_query_parameters(field.type_name(), &format!("{}.", name), depth + 1, visited, path_parameters, parameters)?;
+                        }
+                        continue;
+                    }
                 },
-                "DELETE" => {
-                    path_item.delete = Some(operation);
+                field_descriptor_proto::Type::Enum => self.type_ref(field.type_name()),
+                _ => ReferenceOr::Item(Schema {
+                    schema_data: SchemaData::default(),
+                    schema_kind: SchemaKind::Type(scalar_type_to_schema(field, self.options)?),
+                }),
+            };
+            let schema = if repeated {
+                ReferenceOr::Item(Schema {
+                    schema_data: SchemaData::default(),
+                    schema_kind: SchemaKind::Type(Type::Array(ArrayType {
+                        min_items: None,
+                        max_items: None,
+                        unique_items: false,
+                        items: match schema {
+                            ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
+                            ReferenceOr::Item(item) => ReferenceOr::boxed_item(item),
+                        },
+                    })),
+                })
+            } else {
+                schema
+            };
+
+            parameters.push(ReferenceOr::Item(Parameter::Query {
+                parameter_data: ParameterData {
+                    name,
+                    description: None,
+                    required: false,
+                    deprecated: None,
+                    format: ParameterSchemaOrContent::Schema(schema),
+                    example: None,
+                    examples: IndexMap::new(),
+                    explode: None,
+                    extensions: IndexMap::new(),
                 },
-                _ => {}
-            }
+                allow_reserved: false,
+                style: QueryStyle::Form,
+                allow_empty_value: None,
+            }));
         }
 
-        path_item
+        visited.pop();
+
+        Ok(())
     }
 
     /// Recursively generates an OpenAPI schema from a proto message and its nested messages and enums.
@@ -355,20 +2743,60 @@ impl<'a> OpenAPIGenerator<'a> {
     /// # Important
     /// This function will flatten all nested messages and enums into a single map.
     /// This is because the OpenAPI spec does not support nested messages and enums.
-    pub fn generate_schema_recursive(&self, tl_message: DescriptorProto, mut depth: i32) -> HashMap<String, Schema> {
+    pub fn generate_schema_recursive(&mut self, tl_message: DescriptorProto, mut depth: i32) -> Result<IndexMap<String, Schema>, Error> {
         depth += 1;
-        let mut schema_map = HashMap::new();
-        if depth >= 10 {
-            // Safety: we are not going to exceed the maximum depth of 10
-            return schema_map;
+        let mut schema_map = IndexMap::new();
+        // map entries are rendered as part of the field using them
+        if tl_message.options.as_ref().is_some_and(|options| options.map_entry()) {
+            return Ok(schema_map);
+        }
+        // the recursion itself ends with the nesting of the proto file, this only limits what's emitted
+        if matches!(self.options.flatten_depth, Some(max) if depth as usize > max) {
+            eprintln!("warning: skipping message {}, because it is nested deeper than the flatten depth", tl_message.name());
+            return Ok(schema_map);
         }
-        let message_name = tl_message.name().to_string();
+        let full_name = format!("{}.{}", self.scope, tl_message.name());
+        let message_name = self.schema_name(&full_name);
         let oneof_decl = tl_message.oneof_decl;
+        // annotations like `@go-type time.Time` turn into `x-go-type` extensions of the property
+        let field_extensions = tl_message.field.iter().enumerate().map(|(idx, field)| {
+            let extensions: IndexMap<String, serde_json::Value> = self.annotations_at(&[2, idx as i32]).iter()
+                .filter(|(name, _)| LANG_TYPE_RE.is_match(name))
+                .map(|(name, value)| (format!("x-{}", name), value.clone().into()))
+                .collect();
+            (self.property_name(field), extensions)
+        }).filter(|(_, extensions)| !extensions.is_empty()).collect_vec();
+        // the leading comment of a field describes it, or the trailing one like `string name = 1; // the name`
+        let field_descriptions = tl_message.field.iter().enumerate().filter_map(|(idx, field)| {
+            let path = self.path.iter().chain(&[2, idx as i32]).copied().collect_vec();
+            let location = self.source_info.location.binary_search_by_key(&&path[..], |location| &location.path[..]).ok()?;
+            let comments = Comments::from_location(&self.source_info.location[location]);
+            let description = comment_description(&comments.leading).or_else(|| comment_description(&comments.trailing))?;
+            Some((self.property_name(field), description))
+        }).collect_vec();
+        let field_formats = tl_message.field.iter().enumerate().filter_map(|(idx, field)| {
+            Some((self.property_name(field), self.annotations_at(&[2, idx as i32]).get("format")?.clone()))
+        }).collect_vec();
+        let field_constraints = tl_message.field.iter().enumerate().filter_map(|(idx, field)| {
+            let path = self.path.iter().chain(&[2, idx as i32]).copied().collect_vec();
+            Some((self.property_name(field), self.constraints.get(&path)?.clone()))
+        }).collect_vec();
+        let field_behaviors = tl_message.field.iter().enumerate().filter_map(|(idx, field)| {
+            let path = self.path.iter().chain(&[2, idx as i32]).copied().collect_vec();
+            Some((self.property_name(field), self.field_behaviors.get(&path)?.clone()))
+        }).collect_vec();
 
-        for nested_message in tl_message.nested_type {
-            let schema = self.generate_schema_recursive(nested_message, depth);
-            schema_map.extend(schema);
+        let outer_scope = std::mem::replace(&mut self.scope, full_name.clone());
+        let mut nested_map = IndexMap::new();
+        self.path.push(3);
+        for (idx, nested_message) in tl_message.nested_type.into_iter().enumerate() {
+            self.path.push(idx as i32);
+            let schema = self.generate_schema_recursive(nested_message, depth)?;
+            nested_map.extend(schema);
+            self.path.pop();
         }
+        self.path.pop();
+        self.scope = outer_scope;
 
         type Fields = Vec<FieldDescriptorProto>;
         type OneofFields = MultiMap<i32, FieldDescriptorProto>;
@@ -385,32 +2813,111 @@ impl<'a> OpenAPIGenerator<'a> {
                     Either::Left(field)
                 }
             });
-        let tl_schema = self.generate_fields_schema(&fields, &oneof_fields, &oneof_decl);
+        let mut tl_schema = self.generate_fields_schema(&fields, &oneof_fields, &oneof_decl)?;
+        let annotations = self.annotations_at(&[]);
+        if let Some(order) = annotations.get("order") {
+            order_properties(&mut tl_schema, order, &message_name);
+        }
+        for (property, description) in field_descriptions {
+            // a generated description of the property (like the note of an exclusive oneof) is kept after the comment
+            if let Some(item) = property_schema(&mut tl_schema, &property, true) {
+                item.schema_data.description = match item.schema_data.description.take() {
+                    Some(generated) => Some(format!("{}\n\n{}", description, generated)),
+                    None => Some(description),
+                };
+            }
+        }
+        for (property, extensions) in field_extensions {
+            if let Some(item) = property_schema(&mut tl_schema, &property, true) {
+                item.schema_data.extensions.extend(extensions);
+            }
+        }
+        for (property, format) in field_formats {
+            format_property(&mut tl_schema, &property, &format, &message_name);
+        }
+        for (property, constraints) in field_constraints {
+            constrain_property(&mut tl_schema, &property, &constraints);
+        }
+        for (property, behaviors) in field_behaviors {
+            apply_field_behavior(&mut tl_schema, &property, &behaviors, self.options.all_optional);
+        }
+        // the annotations of the message override the global default
+        let additional_properties = match (annotations.contains_key("closed"), annotations.contains_key("open")) {
+            (true, true) => {
+                eprintln!("warning: message {} is annotated with both @closed and @open, ignoring both", message_name);
+                None
+            }
+            (true, false) => Some(false),
+            (false, true) => Some(true),
+            (false, false) => Some(false).filter(|_| self.options.no_additional_properties),
+        };
+        if let (Some(allowed), SchemaKind::Type(Type::Object(object_type))) = (additional_properties, &mut tl_schema.schema_kind) {
+            object_type.additional_properties = Some(AdditionalProperties::Any(allowed));
+        }
         schema_map.insert(message_name, tl_schema);
+        schema_map.extend(nested_map);
 
         for enum_descriptor in &tl_message.enum_type {
             let enum_schema = self.generate_enum_schema(&enum_descriptor.value);
-            schema_map.insert(enum_descriptor.name().to_string(), enum_schema);
+            let name = self.schema_name(&format!("{}.{}", full_name, enum_descriptor.name()));
+            if schema_map.insert(name.clone(), enum_schema).is_some() {
+                return Err(Error::SchemaCollision(name));
+            }
         }
 
-        schema_map
+        Ok(schema_map)
     }
 
     /// Generates an OpenAPI schema containing an enum, along with a description which contains the possible values.
+    ///
+    /// Values without a number are skipped. An enum without any values results in a plain integer schema.
     pub fn generate_enum_schema(&self, enum_values: &[EnumValueDescriptorProto]) -> Schema {
+        let enum_values = enum_values.iter().filter(|evd| {
+            if evd.number.is_none() {
+                eprintln!("warning: skipping enum value {} because it has no number", evd.name());
+            }
+            evd.number.is_some()
+        }).collect_vec();
+
         let schema_data = SchemaData {
             description: Some(enum_values.iter().map(|e| {
-                format!("{} = {}", e.name(), e.number())
-            }).join("\n\n")),
+                // single values can't be deprecated in OpenAPI, so it is only noted
+                if e.options.as_ref().is_some_and(|options| options.deprecated()) {
+                    format!("{} = {} (deprecated)", e.name(), e.number())
+                } else {
+                    format!("{} = {}", e.name(), e.number())
+                }
+            }).join("\n\n")).filter(|d| !d.is_empty()),
             ..SchemaData::default()
         };
 
+        // `const` only exists since OpenAPI 3.1, while the documents are 3.0
+        if self.options.collapse_single_value_enums && enum_values.len() == 1 {
+            eprintln!("warning: keeping the single value {} of its enum as an enum array, because const needs OpenAPI 3.1", enum_values[0].name());
+        }
+
+        // aliases (`allow_alias`) share a number, but have distinct names
         let integer_type = IntegerType {
-            enumeration: enum_values.iter().map(|evd| evd.number.unwrap() as i64).collect(),
+            enumeration: enum_values.iter().map(|evd| evd.number() as i64).unique().collect(),
             ..IntegerType::default()
         };
+        let string_type = StringType {
+            enumeration: enum_values.iter().map(|evd| evd.name().to_string()).collect(),
+            ..StringType::default()
+        };
 
-        let schema_kind = SchemaKind::Type(Type::Integer(integer_type));
+        let schema_kind = if self.options.enum_as_string_or_int {
+            SchemaKind::AnyOf {
+                any_of: vec![
+                    ReferenceOr::Item(Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Type(Type::Integer(integer_type)) }),
+                    ReferenceOr::Item(Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Type(Type::String(string_type)) }),
+                ],
+            }
+        } else if self.options.enum_as_string {
+            SchemaKind::Type(Type::String(string_type))
+        } else {
+            SchemaKind::Type(Type::Integer(integer_type))
+        };
 
         Schema {
             schema_data,
@@ -424,55 +2931,52 @@ impl<'a> OpenAPIGenerator<'a> {
         fields: &[FieldDescriptorProto],
         oneof_fields: &MultiMap<i32, FieldDescriptorProto>,
         oneof_decl: &[OneofDescriptorProto],
-    ) -> Schema {
-        let schema_data = SchemaData::default();
+    ) -> Result<Schema, Error> {
+        let mut schema_data = SchemaData::default();
         let mut object_type = ObjectType::default();
+        // the members of each oneof, kept regardless of the oneof style so the grouping can be reconstructed
+        let mut oneof_groups = serde_json::Map::new();
 
         for field in fields {
-            let field_name = field.name();
+            let field_name = self.property_name(field);
+            // oneof members never get here, and repeated fields (including maps) are empty arrays (or objects) rather than missing
+            let present = match field.label() {
+                Label::Repeated => !self.options.optional_repeated,
+                _ if self.proto3 => !field.proto3_optional.unwrap_or(false),
+                label => label == Label::Required,
+            };
+            if present && !self.options.all_optional {
+                object_type.required.push(field_name.clone());
+            }
 
-            if field.label() == Label::Repeated {
+            if let Some(entry) = field.type_name.as_ref().and_then(|type_name| self.map_entry(type_name)) {
+                // maps are repeated entry messages in the descriptor, but objects in JSON
+                object_type.properties.insert(field_name, ReferenceOr::boxed_item(self.generate_map_schema(entry)?));
+            } else if field.label() == Label::Repeated {
                 // type is array
-                if field.type_name.is_some() {
+                if let Some(field_type_name) = &field.type_name {
                     // type is a foreign type
                     // it could be a reference to an existing schema type or a proto type
-                    let field_type_name = field.type_name.as_ref().unwrap();
-                    let field_type_name = field_type_name.split('.').last().unwrap().to_string();
                     object_type.properties.insert(
-                        field_name.to_string(),
+                        field_name,
                         ReferenceOr::boxed_item(Schema {
                             schema_kind: SchemaKind::Type(Type::Array(ArrayType {
                                 min_items: None,
                                 max_items: None,
                                 unique_items: false,
-                                items: ReferenceOr::ref_(format!("#/components/schemas/{}", field_type_name.as_str()).as_str()),
+                                items: match self.type_schema(field_type_name) {
+                                    ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
+                                    ReferenceOr::Item(item) => ReferenceOr::boxed_item(item),
+                                },
                             })),
                             schema_data: SchemaData::default(),
                         }),
                     );
                 } else {
-                    let inner_type = match field.r#type() {
-                        field_descriptor_proto::Type::Bool => Type::Boolean {},
-                        field_descriptor_proto::Type::String => Type::String(StringType::default()),
-                        field_descriptor_proto::Type::Double => Type::Number(NumberType::default()),
-                        field_descriptor_proto::Type::Float => Type::Number(NumberType::default()),
-                        field_descriptor_proto::Type::Int32 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        field_descriptor_proto::Type::Int64 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        field_descriptor_proto::Type::Uint32 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        field_descriptor_proto::Type::Uint64 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        _ => Type::String(StringType::default()),
-                    };
+                    let inner_type = scalar_type_to_schema(field, self.options)?;
                     let field_schema: Schema = Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Type(inner_type) };
                     object_type.properties.insert(
-                        field_name.to_string(),
+                        field_name,
                         ReferenceOr::boxed_item(Schema {
                             schema_data: SchemaData::default(),
                             schema_kind: SchemaKind::Type(Type::Array(ArrayType {
@@ -485,39 +2989,35 @@ impl<'a> OpenAPIGenerator<'a> {
                     );
                 }
             } else {
+                // proto3 optional fields tell a missing value apart from the default, like the wrapper types
+                let nullable = field.proto3_optional.unwrap_or(false);
                 // type is object
-                if field.type_name.is_some() {
+                if let Some(field_type_name) = &field.type_name {
                     // type is a foreign type
                     // it could be a reference to an existing schema type or a proto type
-                    let field_type_name = field.type_name.as_ref().unwrap();
-                    let field_type_name = field_type_name.split('.').last().unwrap().to_string();
                     object_type.properties.insert(
-                        field_name.to_string(),
-                        ReferenceOr::ref_(format!("#/components/schemas/{}", field_type_name.as_str()).as_str()),
+                        field_name,
+                        match self.type_schema(field_type_name) {
+                            // nullable next to a reference is ignored, so the reference is wrapped
+                            ReferenceOr::Reference { reference } if nullable => ReferenceOr::boxed_item(Schema {
+                                schema_data: SchemaData { nullable: true, ..SchemaData::default() },
+                                schema_kind: SchemaKind::AllOf { all_of: vec![ReferenceOr::Reference { reference }] },
+                            }),
+                            ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
+                            ReferenceOr::Item(mut item) => {
+                                item.schema_data.nullable |= nullable;
+                                ReferenceOr::boxed_item(item)
+                            }
+                        },
                     );
                 } else {
-                    let inner_type = match field.r#type() {
-                        field_descriptor_proto::Type::Bool => Type::Boolean {},
-                        field_descriptor_proto::Type::String => Type::String(StringType::default()),
-                        field_descriptor_proto::Type::Double => Type::Number(NumberType::default()),
-                        field_descriptor_proto::Type::Float => Type::Number(NumberType::default()),
-                        field_descriptor_proto::Type::Int32 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        field_descriptor_proto::Type::Int64 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        field_descriptor_proto::Type::Uint32 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        field_descriptor_proto::Type::Uint64 => {
-                            Type::Integer(IntegerType::default())
-                        }
-                        _ => Type::String(StringType::default()),
+                    let inner_type = scalar_type_to_schema(field, self.options)?;
+                    let field_schema: Schema = Schema {
+                        schema_data: SchemaData { nullable, ..SchemaData::default() },
+                        schema_kind: SchemaKind::Type(inner_type),
                     };
-                    let field_schema: Schema = Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Type(inner_type) };
                     object_type.properties.insert(
-                        field_name.to_string(),
+                        field_name,
                         ReferenceOr::boxed_item(field_schema),
                     );
                 }
@@ -527,47 +3027,77 @@ impl<'a> OpenAPIGenerator<'a> {
         for (idx, oneof) in oneof_decl.iter().enumerate() {
             let idx = idx as i32;
 
-            let oneofs = match oneof_fields.get_vec(&idx) {
-                Some(fields) => fields,
-                None => continue,
-            };
+            let oneofs = match oneof_fields.get_vec(&idx) {
+                Some(fields) => fields,
+                None => continue,
+            };
+
+            // oneofs have no json_name, so the declaration name is used as-is
+            let field_name = oneof.name();
+            oneof_groups.insert(
+                field_name.to_string(),
+                oneofs.iter().map(|o| serde_json::Value::from(self.property_name(o))).collect(),
+            );
+            // oneofs are located at field 8 of their message
+            let annotations = self.annotations_at(&[8, idx]);
+            let discriminator = annotations.get("discriminator");
+            let mut schema_data = SchemaData::default();
+
+            if discriminator.is_none() && self.options.oneof_style == OneofStyle::Exclusive {
+                for o in oneofs {
+                    let others = oneofs.iter().filter(|other| other.number != o.number).map(|other| self.property_name(other)).join(", ");
+                    let description = format!("Part of oneof {}, mutually exclusive with: {}", field_name, others);
+                    let field_schema = match self.generate_oneof_field_schema(o)? {
+                        // descriptions next to a reference are ignored, so the reference is wrapped
+                        ReferenceOr::Reference { reference } => Schema {
+                            schema_data: SchemaData { description: Some(description), ..SchemaData::default() },
+                            schema_kind: SchemaKind::AllOf { all_of: vec![ReferenceOr::Reference { reference }] },
+                        },
+                        ReferenceOr::Item(mut item) => {
+                            item.schema_data.description = Some(description);
+                            item
+                        }
+                    };
+                    object_type.properties.insert(self.property_name(o), ReferenceOr::boxed_item(field_schema));
+                }
+                continue;
+            }
+
+            let one_of = if discriminator.is_some() || self.options.oneof_style == OneofStyle::Union {
+                if let Some(property_name) = discriminator {
+                    if oneofs.iter().all(|o| o.r#type() == field_descriptor_proto::Type::Message) {
+                        schema_data.discriminator = Some(Discriminator {
+                            property_name: property_name.clone(),
+                            mapping: oneofs.iter()
+                                .filter(|o| !self.beyond_flatten_depth(o.type_name()))
+                                .map(|o| (self.property_name(o), self.schema_ref(o.type_name())))
+                                .collect(),
+                            extensions: IndexMap::new(),
+                        });
+                    } else {
+                        eprintln!("warning: ignoring @discriminator on oneof {}, because not all of its fields are messages", field_name);
+                    }
+                }
 
-            let field_name = oneof.name();
-            let field_schema: Schema = Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::OneOf {
-                one_of: oneofs.iter().map(|o| {
+                oneofs.iter().map(|o| self.generate_oneof_field_schema(o)).collect::<Result<_, Error>>()?
+            } else {
+                oneofs.iter().map(|o| {
                     let mut ind_map: IndexMap<String, ReferenceOr<Box<Schema>>> = IndexMap::new();
-                    ind_map.insert(o.name().to_string(), ReferenceOr::boxed_item(Schema { 
-                        schema_data: SchemaData::default(), 
-                        schema_kind: SchemaKind::Type(match o.r#type() {
-                            field_descriptor_proto::Type::Bool => Type::Boolean {},
-                            field_descriptor_proto::Type::String => Type::String(StringType::default()),
-                            field_descriptor_proto::Type::Double => Type::Number(NumberType::default()),
-                            field_descriptor_proto::Type::Float => Type::Number(NumberType::default()),
-                            field_descriptor_proto::Type::Int32 => {
-                                Type::Integer(IntegerType::default())
-                            }
-                            field_descriptor_proto::Type::Int64 => {
-                                Type::Integer(IntegerType::default())
-                            }
-                            field_descriptor_proto::Type::Uint32 => {
-                                Type::Integer(IntegerType::default())
-                            }
-                            field_descriptor_proto::Type::Uint64 => {
-                                Type::Integer(IntegerType::default())
-                            }
-                            _ => Type::String(StringType::default()),
-                        })
-                    }));
+                    ind_map.insert(self.property_name(o), match self.generate_oneof_field_schema(o)? {
+                        ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
+                        ReferenceOr::Item(item) => ReferenceOr::boxed_item(item),
+                    });
 
-                    ReferenceOr::Item(Schema {
+                    Ok(ReferenceOr::Item(Schema {
                         schema_data: SchemaData::default(),
                         schema_kind: SchemaKind::Type(Type::Object(ObjectType {
                             properties: ind_map,
                             ..ObjectType::default()
                         })),
-                    })
-                }).collect(),
-            } };
+                    }))
+                }).collect::<Result<_, Error>>()?
+            };
+            let field_schema: Schema = Schema { schema_data, schema_kind: SchemaKind::OneOf { one_of } };
 
             object_type.properties.insert(
                 field_name.to_string(),
@@ -575,17 +3105,249 @@ impl<'a> OpenAPIGenerator<'a> {
             );
         }
 
-        let schema_kind = SchemaKind::Type(Type::Object(object_type));
+        if !oneof_groups.is_empty() {
+            schema_data.extensions.insert("x-proto-oneof".to_string(), serde_json::Value::Object(oneof_groups));
+        }
 
-        Schema {
+        let schema_kind = SchemaKind::Type(Type::Object(object_type));
+        let mut schema = Schema {
             schema_data,
             schema_kind,
+        };
+
+        for field in fields {
+            // the cardinality is only implicit in the schema, so the label is kept for tools mapping back to proto
+            let label = match field.label() {
+                Label::Optional => "LABEL_OPTIONAL",
+                Label::Required => "LABEL_REQUIRED",
+                Label::Repeated => "LABEL_REPEATED",
+            };
+            if let Some(item) = property_schema(&mut schema, &self.property_name(field), true) {
+                item.schema_data.extensions.insert("x-proto-label".to_string(), serde_json::Value::from(label));
+            }
+
+            // the keys use one naming convention, the other one is kept next to them where it differs
+            if field.json_name.is_some() && field.json_name() != field.name() {
+                let (extension, name) = if self.options.json_names {
+                    ("x-proto-name", field.name())
+                } else {
+                    ("x-proto-json-name", field.json_name())
+                };
+                if let Some(item) = property_schema(&mut schema, &self.property_name(field), true) {
+                    item.schema_data.extensions.insert(extension.to_string(), serde_json::Value::from(name));
+                }
+            }
+            if field.options.as_ref().is_some_and(|options| options.deprecated()) {
+                if let Some(item) = property_schema(&mut schema, &self.property_name(field), true) {
+                    item.schema_data.deprecated = true;
+                }
+            }
+        }
+
+        Ok(schema)
+    }
+
+    /// Returns the message of a type if it is the entry of a map field, which protoc generates for every `map<K, V>`.
+    fn map_entry(&self, type_name: &str) -> Option<&DescriptorProto> {
+        self.messages.get(type_name)
+            .filter(|message| message.options.as_ref().is_some_and(|options| options.map_entry()))
+    }
+
+    /// Generates the schema of a map field from its entry message, as an object with the values as `additionalProperties`.
+    ///
+    /// JSON only has string keys, so other key types are noted in the description of the schema.
+    pub fn generate_map_schema(&self, entry: &DescriptorProto) -> Result<Schema, Error> {
+        // the key is always field 1 and the value field 2 of the entry
+        let key = entry.field.iter().find(|field| field.number() == 1);
+        let value_schema = match entry.field.iter().find(|field| field.number() == 2) {
+            Some(value) => match &value.type_name {
+                Some(type_name) => self.type_schema(type_name),
+                None => ReferenceOr::Item(Schema {
+                    schema_data: SchemaData::default(),
+                    schema_kind: SchemaKind::Type(scalar_type_to_schema(value, self.options)?),
+                }),
+            },
+            None => {
+                eprintln!("warning: map entry {} has no value field, allowing any value", entry.name());
+                ReferenceOr::Item(Schema {
+                    schema_data: SchemaData::default(),
+                    schema_kind: SchemaKind::Any(Default::default()),
+                })
+            }
+        };
+        let description = key
+            .filter(|key| key.r#type() != field_descriptor_proto::Type::String)
+            .map(|key| format!("The keys are {} values", format!("{:?}", key.r#type()).to_lowercase()));
+
+        Ok(Schema {
+            schema_data: SchemaData { description, ..SchemaData::default() },
+            schema_kind: SchemaKind::Type(Type::Object(ObjectType {
+                additional_properties: Some(AdditionalProperties::Schema(Box::new(value_schema))),
+                ..ObjectType::default()
+            })),
+        })
+    }
+
+    /// Generates the schema of a single field of a oneof, referencing the schema of message and enum types.
+    ///
+    /// Protobuf doesn't allow repeated fields in a oneof, but a malformed descriptor might contain one anyway.
+    /// Such a field is rendered as an array, with a warning.
+    pub fn generate_oneof_field_schema(&self, field: &FieldDescriptorProto) -> Result<ReferenceOr<Schema>, Error> {
+        let schema = match &field.type_name {
+            Some(type_name) => self.type_schema(type_name),
+            None => ReferenceOr::Item(Schema {
+                schema_data: SchemaData::default(),
+                schema_kind: SchemaKind::Type(scalar_type_to_schema(field, self.options)?),
+            }),
+        };
+        if field.label() != Label::Repeated {
+            return Ok(schema);
+        }
+
+        eprintln!("warning: field {} is repeated, but part of a oneof, which protobuf doesn't allow", field.name());
+        Ok(ReferenceOr::Item(Schema {
+            schema_data: SchemaData::default(),
+            schema_kind: SchemaKind::Type(Type::Array(ArrayType {
+                min_items: None,
+                max_items: None,
+                unique_items: false,
+                items: match schema {
+                    ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
+                    ReferenceOr::Item(item) => ReferenceOr::boxed_item(item),
+                },
+            })),
+        }))
+    }
+
+    /// Returns the annotations in the leading comments of the element at the given path, relative to the current location.
+    ///
+    /// Returns no annotations if there is no source info for the element.
+    pub fn annotations_at(&self, relative_path: &[i32]) -> MultiMap<String, String> {
+        let path = self.path.iter().chain(relative_path).copied().collect_vec();
+        match self.source_info.location.binary_search_by_key(&&path[..], |location| &location.path[..]) {
+            Ok(idx) => parse_annotations(&Comments::from_location(&self.source_info.location[idx]).leading),
+            Err(_) => MultiMap::new(),
+        }
+    }
+
+    /// Returns the name of the schema generated for a proto type, honoring the `prefix_schemas` option.
+    ///
+    /// The type name may be fully qualified (like `.helloworld.HelloMessage`). Names which aren't (like the ones given
+    /// in annotations) are resolved like protoc does, from the current scope outwards, otherwise they need to be unique.
+    /// Types without a schema name fall back to the last segment of their name.
+    pub fn schema_name(&self, type_name: &str) -> String {
+        let full_name = self.resolve_type_name(type_name);
+        let name = match self.schema_names.get(&full_name) {
+            Some(name) => name.clone(),
+            None => full_name.rsplit('.').next().unwrap().to_string(),
+        };
+        match &self.options.prefix_schemas {
+            Some(prefix) => format!("{}{}", prefix, name),
+            None => name,
+        }
+    }
+
+    /// Resolves a type name to its fully qualified name, like `.helloworld.HelloMessage`.
+    ///
+    /// Names which aren't fully qualified (like the ones given in annotations) are resolved like protoc does,
+    /// from the current scope outwards, otherwise they need to be unique. Unknown names are returned as they are.
+    fn resolve_type_name(&self, type_name: &str) -> String {
+        if type_name.starts_with('.') {
+            return type_name.to_string();
+        }
+        let scopes = std::iter::successors(Some(self.scope.as_str()), |scope| scope.rfind('.').map(|idx| &scope[..idx]));
+        let suffix = format!(".{}", type_name);
+        let mut candidates = self.schema_names.keys().filter(|name| name.ends_with(&suffix));
+        scopes.map(|scope| format!("{}.{}", scope, type_name))
+            .find(|name| self.schema_names.contains_key(name))
+            .or_else(|| match (candidates.next(), candidates.next()) {
+                (Some(full_name), None) => Some(full_name.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| type_name.to_string())
+    }
+
+    /// Returns the schema of a field with a message or enum type, which is a reference to the schema generated for the type,
+    /// unless it is a well-known type.
+    pub fn type_schema(&self, type_name: &str) -> ReferenceOr<Schema> {
+        match well_known_type_schema(type_name, self.options) {
+            Some(schema) => ReferenceOr::Item(schema),
+            None => self.type_ref(type_name),
+        }
+    }
+
+    /// Returns a reference to the schema generated for a proto type.
+    ///
+    /// Types nested deeper than the `flatten_depth` option have no schema, so they are replaced by a plain object
+    /// (or the scalar of an enum) with a warning, instead of a reference which doesn't resolve.
+    pub fn type_ref(&self, type_name: &str) -> ReferenceOr<Schema> {
+        let full_name = self.resolve_type_name(type_name);
+        if !self.beyond_flatten_depth(&full_name) {
+            return ReferenceOr::ref_(&self.schema_ref(type_name));
+        }
+        eprintln!("warning: {} is nested deeper than the flatten depth, replacing the reference to it", full_name.trim_start_matches('.'));
+        // enums are typed like the schemas generated for them, just without their values
+        let integer = || Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Type(Type::Integer(IntegerType::default())) };
+        let string = || Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Type(Type::String(StringType::default())) };
+        let schema = if self.messages.contains_key(&full_name) {
+            Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::Type(Type::Object(ObjectType::default())) }
+        } else if self.options.enum_as_string_or_int {
+            Schema { schema_data: SchemaData::default(), schema_kind: SchemaKind::AnyOf { any_of: vec![ReferenceOr::Item(integer()), ReferenceOr::Item(string())] } }
+        } else if self.options.enum_as_string {
+            string()
+        } else {
+            integer()
+        };
+
+        ReferenceOr::Item(schema)
+    }
+
+    /// Whether a message or enum is nested deeper than the `flatten_depth` option, so no schema is generated for it.
+    ///
+    /// Enums are on the level of the message containing them, top-level enums are never skipped.
+    fn beyond_flatten_depth(&self, full_name: &str) -> bool {
+        let max = match self.options.flatten_depth {
+            Some(max) => max,
+            None => return false,
+        };
+        let prefixes = full_name.match_indices('.').map(|(idx, _)| &full_name[..idx]).chain(std::iter::once(full_name));
+        let level = prefixes.filter(|prefix| self.messages.contains_key(*prefix)).count();
+
+        level > max
+    }
+
+    /// Returns a reference to the schema generated for a proto type.
+    pub fn schema_ref(&self, type_name: &str) -> String {
+        self.component_ref("schemas", &self.schema_name(type_name))
+    }
+
+    /// Returns the reference to a component of the given kind (like `schemas`), honoring the `ref_style` option.
+    pub fn component_ref(&self, kind: &str, name: &str) -> String {
+        let base = match self.options.ref_style {
+            RefStyle::Internal => "",
+            RefStyle::Bundled => self.options.ref_base.as_deref().unwrap_or(DEFAULT_BUNDLED_REF_BASE),
+            // the base is checked to be present when parsing the options
+            RefStyle::External => self.options.ref_base.as_deref().unwrap_or_default(),
+        };
+
+        format!("{}#/components/{}/{}", base, kind, name)
+    }
+
+    /// Returns the property name of a field, honoring the `json_names` option.
+    pub fn property_name(&self, field: &FieldDescriptorProto) -> String {
+        if self.options.json_names && field.json_name.is_some() {
+            field.json_name().to_string()
+        } else {
+            field.name().to_string()
         }
     }
 
     /// Generate a service from a service descriptor. Contains comments to the service and its methods.
-    pub fn generate_service(&mut self, service: ServiceDescriptorProto) -> Service {
+    ///
+    /// Fails if a method has no name, input or output type.
+    pub fn generate_service(&mut self, service: ServiceDescriptorProto) -> Result<Service, Error> {
         let name = service.name().to_owned();
+        let service_name = name.clone();
         let comments = Comments::from_location(self.location());
 
         self.path.push(2);
@@ -598,15 +3360,16 @@ impl<'a> OpenAPIGenerator<'a> {
                 let comments = Comments::from_location(self.location());
                 self.path.pop();
 
-                let name = method.name.take().unwrap();
-                let input_proto_type = method.input_type.take().unwrap();
-                let output_proto_type = method.output_type.take().unwrap();
+                let missing = |what| Error::InvalidDescriptor(format!("a method of service {} has no {}", service_name, what));
+                let name = method.name.take().ok_or_else(|| missing("name"))?;
+                let input_proto_type = method.input_type.take().ok_or_else(|| missing("input type"))?;
+                let output_proto_type = method.output_type.take().ok_or_else(|| missing("output type"))?;
                 let input_type = "".to_string();
                 let output_type = "".to_string();
                 let client_streaming = method.client_streaming();
                 let server_streaming = method.server_streaming();
 
-                Method {
+                Ok(Method {
                     name: name.clone(),
                     proto_name: name,
                     comments,
@@ -617,18 +3380,862 @@ impl<'a> OpenAPIGenerator<'a> {
                     options: method.options.unwrap_or_default(),
                     client_streaming,
                     server_streaming,
-                }
+                })
             })
-            .collect();
+            .collect::<Result<Vec<_>, Error>>();
         self.path.pop();
 
-        Service {
+        Ok(Service {
             name: name.clone(),
             proto_name: name,
-            package: "".to_string(),
+            // the scope of the generator is the package, with a leading dot
+            package: self.scope.trim_start_matches('.').to_string(),
             comments,
-            methods,
+            methods: methods?,
             options: service.options.unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_types::EnumDescriptorProto;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Compiles a proto file with protoc and generates a document from it, as JSON for easier assertions.
+    fn generate_proto(source: &str, options: &GeneratorOptions) -> Result<Value, Error> {
+        generate_proto_with_imports(source, &[], options)
+    }
+
+    /// Like `generate_proto`, with files which `test.proto` can import, given by their path and contents.
+    fn generate_proto_with_imports(source: &str, imports: &[(&str, &str)], options: &GeneratorOptions) -> Result<Value, Error> {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in imports {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        let proto = dir.path().join("test.proto");
+        std::fs::write(&proto, source).unwrap();
+        let openapi = OpenAPIGenerator::generate(&mut Config::new(), &[&proto], &[dir.path()], options)?;
+
+        Ok(serde_json::to_value(openapi).unwrap())
+    }
+
+    /// Generates a document from hand-made descriptors, like a descriptor set written by another tool.
+    fn generate_descriptors(files: Vec<FileDescriptorProto>, options: &GeneratorOptions) -> Result<Value, Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let set = dir.path().join("set.pb");
+        std::fs::write(&set, FileDescriptorSet { file: files }.encode_to_vec()).unwrap();
+        let options = GeneratorOptions { descriptor_sets: vec![set], ..options.clone() };
+        let openapi = OpenAPIGenerator::generate(&mut Config::new(), &[] as &[PathBuf], &[] as &[PathBuf], &options)?;
+
+        Ok(serde_json::to_value(openapi).unwrap())
+    }
+
+    #[test]
+    fn oneof_members_use_json_names() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Contact {
+                oneof contact_method {
+                    string email_address = 1;
+                    string phone_number = 2;
+                }
+            }
+        "#;
+        let options = GeneratorOptions { json_names: true, allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        let oneof = &doc["components"]["schemas"]["Contact"]["properties"]["contact_method"];
+        assert_eq!(oneof["oneOf"][0]["properties"]["emailAddress"]["type"], json!("string"));
+        assert_eq!(oneof["oneOf"][1]["properties"]["phoneNumber"]["type"], json!("string"));
+        assert_eq!(doc["components"]["schemas"]["Contact"]["x-proto-oneof"], json!({ "contact_method": ["emailAddress", "phoneNumber"] }));
+    }
+
+    #[test]
+    fn enums_without_numbers_or_values_dont_panic() {
+        let value = |name: &str, number: Option<i32>| EnumValueDescriptorProto { name: Some(name.to_string()), number, options: None };
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            enum_type: vec![
+                EnumDescriptorProto {
+                    name: Some("Broken".to_string()),
+                    value: vec![value("UNNUMBERED", None), value("NUMBERED", Some(1))],
+                    ..EnumDescriptorProto::default()
+                },
+                EnumDescriptorProto { name: Some("Empty".to_string()), ..EnumDescriptorProto::default() },
+            ],
+            ..FileDescriptorProto::default()
+        };
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_descriptors(vec![file], &options).unwrap();
+
+        let broken = &doc["components"]["schemas"]["Broken"];
+        assert_eq!(broken["enum"], json!([1]));
+        assert_eq!(broken["description"], json!("NUMBERED = 1"));
+        let empty = &doc["components"]["schemas"]["Empty"];
+        assert_eq!(empty["type"], json!("integer"));
+        assert!(empty.get("enum").is_none());
+        assert!(empty.get("description").is_none());
+    }
+
+    #[test]
+    fn descriptors_without_source_info_generate_without_comments() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            // A user of the API.
+            message User {
+                // The name of the user.
+                string name = 1;
+                // @deprecated
+                Address address = 2;
+                message Address { string city = 1; }
+            }
+        "#;
+        let dir = tempfile::tempdir().unwrap();
+        let proto = dir.path().join("test.proto");
+        std::fs::write(&proto, source).unwrap();
+        let raw = Config::new().get_raw_descriptor(&[&proto], &[dir.path()]).unwrap();
+        let mut files = FileDescriptorSet::decode(&*raw).unwrap().file;
+        for file in &mut files {
+            file.source_code_info = None;
+        }
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_descriptors(files, &options).unwrap();
+
+        let user = &doc["components"]["schemas"]["User"];
+        assert!(user.get("description").is_none());
+        assert_eq!(user["properties"]["name"]["type"], json!("string"));
+        assert!(user["properties"]["name"].get("description").is_none());
+        assert!(user["properties"]["address"].get("deprecated").is_none());
+        assert_eq!(doc["components"]["schemas"]["User_Address"]["properties"]["city"]["type"], json!("string"));
+    }
+
+    /// A proto file with messages nested `levels` deep, each referencing the next one and a nested enum.
+    fn nested_proto(levels: usize) -> String {
+        let mut body = String::new();
+        for level in (1..=levels).rev() {
+            body = format!(
+                "message Level{level} {{ {body} enum Kind{level} {{ KIND{level}_UNSPECIFIED = 0; }} Kind{level} kind = 1; {child} }}",
+                level = level,
+                body = body,
+                child = if level < levels { format!("Level{}.Level{} child = 2;", level, level + 1) } else { String::new() },
+            );
+        }
+        format!("syntax = \"proto3\"; package test; {} message Root {{ Level1.Level2.Level3 deep = 1; }}", body)
+    }
+
+    /// Asserts that every `$ref` of a document points to one of its schemas.
+    fn assert_refs_resolve(doc: &Value) {
+        let mut refs = Vec::new();
+        collect_schema_refs(doc, &mut refs);
+        for name in refs {
+            assert!(doc["components"]["schemas"].get(&name).is_some(), "dangling reference to {}", name);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_messages_are_all_generated() {
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(&nested_proto(12), &options).unwrap();
+
+        let deepest = (2..=12).fold("Level1".to_string(), |name, level| format!("{}_Level{}", name, level));
+        assert!(doc["components"]["schemas"].get(&deepest).is_some());
+        assert!(doc["components"]["schemas"].get(format!("{}_Kind12", deepest)).is_some());
+        assert_refs_resolve(&doc);
+    }
+
+    #[test]
+    fn flatten_depth_replaces_references_to_skipped_types() {
+        let options = GeneratorOptions { allow_empty_paths: true, flatten_depth: Some(2), ..GeneratorOptions::default() };
+        let doc = generate_proto(&nested_proto(4), &options).unwrap();
+
+        let schemas = &doc["components"]["schemas"];
+        assert!(schemas.get("Level1_Level2").is_some());
+        assert!(schemas.get("Level1_Level2_Kind2").is_some());
+        assert!(schemas.get("Level1_Level2_Level3").is_none());
+        assert!(schemas.get("Level1_Level2_Level3_Kind3").is_none());
+        assert_eq!(schemas["Level1_Level2"]["properties"]["child"]["type"], json!("object"));
+        assert_eq!(schemas["Root"]["properties"]["deep"]["type"], json!("object"));
+        assert_refs_resolve(&doc);
+    }
+
+    #[test]
+    fn flatten_depth_types_skipped_enums_like_their_schemas() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Outer { message Inner { enum Kind { KIND_UNSPECIFIED = 0; } } }
+            message Root { Outer.Inner.Kind kind = 1; }
+        "#;
+        let kind = |options: GeneratorOptions| {
+            let options = GeneratorOptions { allow_empty_paths: true, flatten_depth: Some(1), ..options };
+            generate_proto(source, &options).unwrap()["components"]["schemas"]["Root"]["properties"]["kind"].clone()
+        };
+
+        let label = "LABEL_OPTIONAL";
+        assert_eq!(kind(GeneratorOptions::default()), json!({ "type": "integer", "x-proto-label": label }));
+        assert_eq!(kind(GeneratorOptions { enum_as_string: true, ..GeneratorOptions::default() }), json!({ "type": "string", "x-proto-label": label }));
+        assert_eq!(
+            kind(GeneratorOptions { enum_as_string_or_int: true, ..GeneratorOptions::default() }),
+            json!({ "anyOf": [{ "type": "integer" }, { "type": "string" }], "x-proto-label": label }),
+        );
+    }
+
+    #[test]
+    fn repeated_oneof_members_become_arrays() {
+        let field = |name: &str, number: i32, field_type: field_descriptor_proto::Type, type_name: Option<&str>| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Repeated as i32),
+            r#type: Some(field_type as i32),
+            type_name: type_name.map(str::to_string),
+            oneof_index: Some(0),
+            ..FieldDescriptorProto::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![
+                DescriptorProto { name: Some("Item".to_string()), ..DescriptorProto::default() },
+                DescriptorProto {
+                    name: Some("Choice".to_string()),
+                    field: vec![
+                        field("tags", 1, field_descriptor_proto::Type::String, None),
+                        field("items", 2, field_descriptor_proto::Type::Message, Some(".test.Item")),
+                    ],
+                    oneof_decl: vec![OneofDescriptorProto { name: Some("value".to_string()), options: None }],
+                    ..DescriptorProto::default()
+                },
+            ],
+            ..FileDescriptorProto::default()
+        };
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_descriptors(vec![file], &options).unwrap();
+
+        let one_of = &doc["components"]["schemas"]["Choice"]["properties"]["value"]["oneOf"];
+        assert_eq!(one_of[0]["properties"]["tags"], json!({ "type": "array", "items": { "type": "string" } }));
+        assert_eq!(one_of[1]["properties"]["items"], json!({ "type": "array", "items": { "$ref": "#/components/schemas/Item" } }));
+    }
+
+    #[test]
+    fn empty_components_are_omitted() {
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto("syntax = \"proto3\"; package test;", &options).unwrap();
+        assert!(doc.get("components").is_none());
+
+        let doc = generate_proto("syntax = \"proto3\"; package test; message User {}", &options).unwrap();
+        assert_eq!(doc["components"], json!({ "schemas": { "User": { "type": "object" } } }));
+    }
+
+    /// A file with a message field which lacks its type name, like a malformed descriptor set might contain.
+    fn missing_type_name_file() -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Broken".to_string()),
+                field: vec![FieldDescriptorProto {
+                    name: Some("payload".to_string()),
+                    number: Some(1),
+                    label: Some(Label::Optional as i32),
+                    r#type: Some(field_descriptor_proto::Type::Message as i32),
+                    ..FieldDescriptorProto::default()
+                }],
+                ..DescriptorProto::default()
+            }],
+            ..FileDescriptorProto::default()
+        }
+    }
+
+    #[test]
+    fn strict_fails_on_missing_type_names() {
+        let options = GeneratorOptions { allow_empty_paths: true, strict: true, ..GeneratorOptions::default() };
+        let err = generate_descriptors(vec![missing_type_name_file()], &options).unwrap_err();
+
+        assert!(matches!(&err, Error::MissingTypeName { field, .. } if field == "payload"), "unexpected error {}", err);
+        assert_eq!(err.to_string(), "field payload has the type Message, but no type name");
+    }
+
+    #[test]
+    fn missing_type_names_fall_back_to_objects() {
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_descriptors(vec![missing_type_name_file()], &options).unwrap();
+
+        let payload = &doc["components"]["schemas"]["Broken"]["properties"]["payload"];
+        assert_eq!(payload["type"], json!("object"));
+        assert!(payload.get("$ref").is_none() && payload.get("allOf").is_none(), "unexpected reference in {}", payload);
+    }
+
+    #[test]
+    fn path_items_are_stable() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Item { string org = 1; string user = 2; int32 item = 3; }
+            service Items {
+                // DELETE /orgs/{org:string}/users/{user:string}/items/{item:int}
+                rpc DeleteItem(Item) returns (Item);
+                // GET /orgs/{org:string}/users/{user:string}/items/{item:int}
+                rpc GetItem(Item) returns (Item);
+                // PATCH /orgs/{org:string}/users/{user:string}/items/{item:int} + BODY
+                rpc UpdateItem(Item) returns (Item);
+                // GET /orgs/{org:string}/users/{user:string}/items/{item:int}
+                rpc GetItemAgain(Item) returns (Item);
+            }
+        "#;
+        let options = GeneratorOptions::default();
+        let doc = generate_proto(source, &options).unwrap();
+
+        let path_item = &doc["paths"]["/orgs/{org}/users/{user}/items/{item}"];
+        let names: Vec<&Value> = path_item["parameters"].as_array().unwrap().iter().map(|parameter| &parameter["name"]).collect();
+        assert_eq!(names, [&json!("org"), &json!("user"), &json!("item")]);
+        assert_eq!(path_item["get"]["operationId"], json!("Items_GetItem"));
+        assert_eq!(path_item["delete"]["operationId"], json!("Items_DeleteItem"));
+        assert_eq!(path_item["patch"]["operationId"], json!("Items_UpdateItem"));
+        for _ in 0..5 {
+            assert_eq!(generate_proto(source, &options).unwrap(), doc);
+        }
+    }
+
+    /// Returns the reference of a schema, looking through the `allOf` used to give a reference siblings.
+    fn reference_of(schema: &Value) -> Value {
+        schema.get("allOf").map_or(&schema["$ref"], |all_of| &all_of[0]["$ref"]).clone()
+    }
+
+    /// Generates a document with the given ref style and returns the reference of a field and of a response.
+    fn refs_with_style(ref_style: RefStyle, ref_base: Option<&str>) -> (Value, Value) {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Address { string city = 1; }
+            message User { Address address = 1; }
+            service Users {
+                // GET /users
+                rpc GetUser(User) returns (User);
+            }
+        "#;
+        let options = GeneratorOptions { ref_style, ref_base: ref_base.map(str::to_string), ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        (
+            reference_of(&doc["components"]["schemas"]["User"]["properties"]["address"]),
+            reference_of(&doc["paths"]["/users"]["get"]["responses"]["200"]["content"]["application/json"]["schema"]),
+        )
+    }
+
+    #[test]
+    fn internal_refs_point_into_the_document() {
+        let (field, response) = refs_with_style(RefStyle::Internal, None);
+        assert_eq!(field, json!("#/components/schemas/Address"));
+        assert_eq!(response, json!("#/components/schemas/User"));
+    }
+
+    #[test]
+    fn bundled_refs_point_to_the_components_file() {
+        let (field, response) = refs_with_style(RefStyle::Bundled, None);
+        assert_eq!(field, json!("./components.yaml#/components/schemas/Address"));
+        assert_eq!(response, json!("./components.yaml#/components/schemas/User"));
+
+        let (field, _) = refs_with_style(RefStyle::Bundled, Some("./shared.yaml"));
+        assert_eq!(field, json!("./shared.yaml#/components/schemas/Address"));
+    }
+
+    #[test]
+    fn external_refs_point_to_the_base_url() {
+        let (field, response) = refs_with_style(RefStyle::External, Some("https://example.com/api.yaml"));
+        assert_eq!(field, json!("https://example.com/api.yaml#/components/schemas/Address"));
+        assert_eq!(response, json!("https://example.com/api.yaml#/components/schemas/User"));
+    }
+
+    #[test]
+    fn every_field_has_its_label() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Address { string city = 1; }
+            message User { Address address = 1; repeated string tags = 2; }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        let properties = &doc["components"]["schemas"]["User"]["properties"];
+        // siblings of a reference are ignored, so it is wrapped
+        assert_eq!(properties["address"], json!({ "x-proto-label": "LABEL_OPTIONAL", "allOf": [{ "$ref": "#/components/schemas/Address" }] }));
+        assert_eq!(properties["tags"]["x-proto-label"], json!("LABEL_REPEATED"));
+    }
+
+    #[test]
+    fn trim_leading_package_only_trims_whole_segments() {
+        let file = |package: &str| FileDescriptorProto {
+            name: Some(format!("{}.proto", package)),
+            package: Some(package.to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![DescriptorProto { name: Some("User".to_string()), ..DescriptorProto::default() }],
+            ..FileDescriptorProto::default()
+        };
+        let options = GeneratorOptions {
+            allow_empty_paths: true,
+            schema_naming: SchemaNaming::Full,
+            trim_leading_package: Some("acme.v1".to_string()),
+            ..GeneratorOptions::default()
+        };
+        let doc = generate_descriptors(vec![file("acme.v1"), file("acme.v1beta"), file("acme.v1.admin")], &options).unwrap();
+
+        let names: Vec<_> = doc["components"]["schemas"].as_object().unwrap().keys().cloned().collect();
+        assert_eq!(names, ["User", "acme_v1beta_User", "admin_User"]);
+    }
+
+    #[test]
+    fn oneof_members_keep_descriptions_and_formats() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Address { string city = 1; }
+            message Contact {
+                oneof target {
+                    // The day to call.
+                    // @format date
+                    string day = 1;
+                    // Where to write to.
+                    Address address = 2;
+                }
+            }
+        "#;
+        let day = json!({ "type": "string", "format": "date", "description": "The day to call." });
+        let address = json!({ "description": "Where to write to.", "allOf": [{ "$ref": "#/components/schemas/Address" }] });
+
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+        let one_of = &doc["components"]["schemas"]["Contact"]["properties"]["target"]["oneOf"];
+        assert_eq!(one_of[0]["properties"]["day"], day);
+        assert_eq!(one_of[1]["properties"]["address"], address);
+
+        let options = GeneratorOptions { allow_empty_paths: true, oneof_style: OneofStyle::Union, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+        let one_of = &doc["components"]["schemas"]["Contact"]["properties"]["target"]["oneOf"];
+        assert_eq!(one_of[0], day);
+        assert_eq!(one_of[1], address);
+    }
+
+    #[test]
+    fn patch_with_body_has_a_request_body() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message User { string id = 1; string name = 2; }
+            service Users {
+                // PATCH /users/{id:string} + BODY
+                rpc UpdateUser(User) returns (User);
+            }
+        "#;
+        let doc = generate_proto(source, &GeneratorOptions::default()).unwrap();
+
+        let path_item = &doc["paths"]["/users/{id}"];
+        assert_eq!(path_item["parameters"][0]["name"], json!("id"));
+        let operation = &path_item["patch"];
+        assert_eq!(operation["operationId"], json!("Users_UpdateUser"));
+        assert_eq!(operation["requestBody"]["content"]["application/json"]["schema"]["$ref"], json!("#/components/schemas/User"));
+    }
+
+    #[test]
+    fn single_value_enums_stay_enum_arrays() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            enum Placeholder { PLACEHOLDER_UNSPECIFIED = 0; }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, collapse_single_value_enums: true, enum_as_string: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        let placeholder = &doc["components"]["schemas"]["Placeholder"];
+        assert_eq!(placeholder["enum"], json!(["PLACEHOLDER_UNSPECIFIED"]));
+        assert!(placeholder.get("const").is_none());
+    }
+
+    #[test]
+    fn repeated_fields_are_required() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message User { optional string name = 1; repeated string tags = 2; }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        assert_eq!(doc["components"]["schemas"]["User"]["required"], json!(["tags"]));
+
+        let options = GeneratorOptions { allow_empty_paths: true, optional_repeated: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        assert!(doc["components"]["schemas"]["User"].get("required").is_none());
+    }
+
+    #[test]
+    fn delete_with_status_204_has_no_content() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message User { string id = 1; }
+            message Empty {}
+            service Users {
+                // DELETE /users/{id:string} STATUS 204
+                rpc DeleteUser(User) returns (Empty);
+            }
+        "#;
+        let doc = generate_proto(source, &GeneratorOptions::default()).unwrap();
+
+        let responses = doc["paths"]["/users/{id}"]["delete"]["responses"].as_object().unwrap();
+        assert_eq!(responses.keys().collect_vec(), ["204"]);
+        assert!(responses["204"].get("content").is_none());
+    }
+
+    #[test]
+    fn two_get_routes_of_one_method_get_their_own_paths() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message GetUserRequest { string id = 1; }
+            message User { string id = 1; }
+            service Users {
+                // GET /v1/users/{id:string}
+                // GET /users/{id:string}
+                rpc GetUser(GetUserRequest) returns (User);
+            }
+        "#;
+        let doc = generate_proto(source, &GeneratorOptions::default()).unwrap();
+
+        let paths = doc["paths"].as_object().unwrap();
+        assert_eq!(paths.keys().collect_vec(), ["/users/{id}", "/v1/users/{id}"]);
+        for (path, operation_id) in [("/v1/users/{id}", "Users_GetUser"), ("/users/{id}", "Users_GetUser_2")] {
+            assert_eq!(paths[path]["get"]["operationId"], json!(operation_id));
+            assert_eq!(paths[path]["parameters"][0]["name"], json!("id"));
+            assert_eq!(paths[path]["get"]["responses"]["200"]["content"]["application/json"]["schema"]["$ref"], json!("#/components/schemas/User"));
+        }
+    }
+
+    #[test]
+    fn nested_query_parameters_use_dotted_names() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Filter { string name = 1; int32 age = 2; Filter parent = 3; }
+            message ListUsersRequest { Filter filter = 1; int32 page = 2; }
+            message Users { repeated string names = 1; }
+            service UserService {
+                // GET /users
+                rpc ListUsers(ListUsersRequest) returns (Users);
+            }
+        "#;
+        let parameter_names = |query_depth| {
+            let options = GeneratorOptions { query_params: true, query_depth, ..GeneratorOptions::default() };
+            let doc = generate_proto(source, &options).unwrap();
+            doc["paths"]["/users"]["get"]["parameters"].as_array().unwrap().iter()
+                .map(|parameter| parameter["name"].as_str().unwrap().to_string())
+                .collect_vec()
+        };
+
+        // the recursion through `parent` ends at the second Filter
+        assert_eq!(parameter_names(None), ["filter.name", "filter.age", "page"]);
+        assert_eq!(parameter_names(Some(1)), ["page"]);
+    }
+
+    #[test]
+    fn discriminated_oneofs_map_to_message_schemas() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Cat { string type = 1; }
+            message Dog { string type = 1; }
+            message Pet {
+                // @discriminator type
+                oneof kind {
+                    Cat cat = 1;
+                    Dog dog = 2;
+                }
+            }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        let kind = &doc["components"]["schemas"]["Pet"]["properties"]["kind"];
+        assert_eq!(kind["discriminator"], json!({
+            "propertyName": "type",
+            "mapping": { "cat": "#/components/schemas/Cat", "dog": "#/components/schemas/Dog" },
+        }));
+        assert_eq!(kind["oneOf"], json!([{ "$ref": "#/components/schemas/Cat" }, { "$ref": "#/components/schemas/Dog" }]));
+    }
+
+    #[test]
+    fn enums_as_string_or_int_accept_both() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            enum Status { STATUS_UNSPECIFIED = 0; STATUS_ACTIVE = 1; }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, enum_as_string_or_int: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        let status = &doc["components"]["schemas"]["Status"];
+        assert_eq!(status["anyOf"], json!([
+            { "type": "integer", "enum": [0, 1] },
+            { "type": "string", "enum": ["STATUS_UNSPECIFIED", "STATUS_ACTIVE"] },
+        ]));
+        assert_eq!(status["description"], json!("STATUS_UNSPECIFIED = 0\n\nSTATUS_ACTIVE = 1"));
+    }
+
+    /// The part of `buf/validate/validate.proto` used by the tests, with the same field numbers.
+    const BUF_VALIDATE_PROTO: &str = r#"
+        syntax = "proto3";
+        package buf.validate;
+        import "google/protobuf/descriptor.proto";
+        extend google.protobuf.FieldOptions { FieldConstraints field = 1159; }
+        message FieldConstraints { Int32Rules int32 = 3; StringRules string = 14; RepeatedRules repeated = 18; }
+        message Int32Rules { optional int32 const = 1; optional int32 lt = 2; optional int32 lte = 3; optional int32 gt = 4; optional int32 gte = 5; }
+        message StringRules { optional uint64 min_len = 2; optional uint64 max_len = 3; optional string pattern = 6; }
+        message RepeatedRules { optional uint64 min_items = 1; optional uint64 max_items = 2; optional bool unique = 3; }
+    "#;
+
+    #[test]
+    fn buf_validate_constraints_are_applied() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            import "buf/validate/validate.proto";
+            message User {
+                string name = 1 [(buf.validate.field).string = { min_len: 1, max_len: 64, pattern: "^[a-z]+$" }];
+                int32 age = 2 [(buf.validate.field).int32 = { gte: 0, lt: 150 }];
+                repeated string tags = 3 [(buf.validate.field).repeated = { max_items: 10, unique: true }];
+            }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto_with_imports(source, &[("buf/validate/validate.proto", BUF_VALIDATE_PROTO)], &options).unwrap();
+
+        let properties = &doc["components"]["schemas"]["User"]["properties"];
+        assert_eq!(properties["name"], json!({
+            "type": "string", "minLength": 1, "maxLength": 64, "pattern": "^[a-z]+$", "x-proto-label": "LABEL_OPTIONAL",
+        }));
+        assert_eq!(properties["age"], json!({
+            "type": "integer", "format": "int32", "minimum": 0, "maximum": 150, "exclusiveMaximum": true, "x-proto-label": "LABEL_OPTIONAL",
+        }));
+        assert_eq!(properties["tags"], json!({
+            "type": "array", "items": { "type": "string" }, "maxItems": 10, "uniqueItems": true, "x-proto-label": "LABEL_REPEATED",
+        }));
+    }
+
+    #[test]
+    fn callbacks_only_have_bodies_for_methods_taking_one() {
+        let source = |other_method: &str| format!(r#"
+            syntax = "proto3";
+            package test;
+            message Event {{ string id = 1; }}
+            message Subscription {{ string url = 1; }}
+            service Hooks {{
+                // POST /subscriptions
+                // @callback onPing GET {{$request.body#/url}} Event
+                // @callback onChange PATCH {{$request.body#/url}} Event
+                rpc Subscribe(Subscription) returns (Subscription);
+                // POST /others
+                // @callback onPing {} {{$request.body#/url}} Event
+                rpc SubscribeOther(Subscription) returns (Subscription);
+            }}
+        "#, other_method);
+        let doc = generate_proto(&source("GET"), &GeneratorOptions::default()).unwrap();
+
+        let callbacks = &doc["components"]["callbacks"];
+        let ping = &callbacks["onPing"]["{$request.body#/url}"]["get"];
+        assert!(ping.get("requestBody").is_none());
+        assert_eq!(ping["parameters"][0]["name"], json!("id"));
+        let change = &callbacks["onChange"]["{$request.body#/url}"]["patch"];
+        assert_eq!(change["requestBody"]["content"]["application/json"]["schema"]["$ref"], json!("#/components/schemas/Event"));
+
+        let err = generate_proto(&source("POST"), &GeneratorOptions::default()).unwrap_err();
+        assert!(matches!(&err, Error::CallbackCollision(name) if name == "onPing"), "unexpected error {}", err);
+    }
+
+    #[test]
+    fn operation_ids_differing_in_case_stay_distinct() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message User { string id = 1; }
+            service Users {
+                // GET /users/{id:string}
+                rpc getUser(User) returns (User);
+                // GET /v2/users/{id:string}
+                rpc GetUser(User) returns (User);
+            }
+        "#;
+        let operation_ids = |operation_id_case| {
+            let doc = generate_proto(source, &GeneratorOptions { operation_id_case, ..GeneratorOptions::default() }).unwrap();
+            ["/users/{id}", "/v2/users/{id}"].map(|path| doc["paths"][path]["get"]["operationId"].as_str().unwrap().to_string())
+        };
+
+        assert_eq!(operation_ids(None), ["Users_getUser", "Users_GetUser"]);
+        assert_eq!(operation_ids(Some(OperationIdCase::Camel)), ["usersGetUser", "usersGetUser2"]);
+        assert_eq!(operation_ids(Some(OperationIdCase::Pascal)), ["UsersGetUser", "UsersGetUser2"]);
+        assert_eq!(operation_ids(Some(OperationIdCase::Snake)), ["users_get_user", "users_get_user_2"]);
+    }
+
+    /// The part of protoc-gen-validate's `validate/validate.proto` used by the tests, with the same field numbers.
+    const PGV_VALIDATE_PROTO: &str = r#"
+        syntax = "proto2";
+        package validate;
+        import "google/protobuf/descriptor.proto";
+        extend google.protobuf.FieldOptions { optional FieldRules rules = 1071; }
+        message FieldRules { optional DoubleRules double = 2; optional Int64Rules int64 = 4; optional StringRules string = 14; optional RepeatedRules repeated = 18; }
+        message DoubleRules { optional double const = 1; optional double lt = 2; optional double lte = 3; optional double gt = 4; optional double gte = 5; }
+        message Int64Rules { optional int64 const = 1; optional int64 lt = 2; optional int64 lte = 3; optional int64 gt = 4; optional int64 gte = 5; }
+        message StringRules {
+            optional uint64 min_len = 2; optional uint64 max_len = 3; optional string pattern = 6;
+            optional bool email = 12; optional bool uuid = 22;
+        }
+        message RepeatedRules { optional uint64 min_items = 1; optional uint64 max_items = 2; optional bool unique = 3; }
+    "#;
+
+    #[test]
+    fn validate_rules_constrain_repeated_fields() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            import "validate/validate.proto";
+            message Tag { string name = 1; }
+            message Post {
+                repeated int32 scores = 1 [(validate.rules).repeated = { min_items: 1, max_items: 5, unique: true }];
+                repeated Tag tags = 2 [(validate.rules).repeated = { max_items: 3, unique: true }];
+                repeated string labels = 3;
+            }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto_with_imports(source, &[("validate/validate.proto", PGV_VALIDATE_PROTO)], &options).unwrap();
+
+        let properties = &doc["components"]["schemas"]["Post"]["properties"];
+        assert_eq!(properties["scores"], json!({
+            "type": "array", "items": { "type": "integer", "format": "int32" }, "minItems": 1, "maxItems": 5, "uniqueItems": true,
+            "x-proto-label": "LABEL_REPEATED",
+        }));
+        assert_eq!(properties["tags"], json!({
+            "type": "array", "items": { "$ref": "#/components/schemas/Tag" }, "maxItems": 3, "uniqueItems": true,
+            "x-proto-label": "LABEL_REPEATED",
+        }));
+        assert_eq!(properties["labels"], json!({
+            "type": "array", "items": { "type": "string" }, "x-proto-label": "LABEL_REPEATED",
+        }));
+    }
+
+    #[test]
+    fn validate_rules_constrain_strings() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            import "validate/validate.proto";
+            message User {
+                string name = 1 [(validate.rules).string = { min_len: 1, max_len: 64, pattern: "^[a-z]+$" }];
+                string handle = 2 [(validate.rules).string.pattern = "^(?!admin)[a-z]+$"];
+                string email = 3 [(validate.rules).string.email = true];
+                string id = 4 [(validate.rules).string.uuid = true];
+            }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto_with_imports(source, &[("validate/validate.proto", PGV_VALIDATE_PROTO)], &options).unwrap();
+
+        let properties = &doc["components"]["schemas"]["User"]["properties"];
+        assert_eq!(properties["name"], json!({
+            "type": "string", "minLength": 1, "maxLength": 64, "pattern": "^[a-z]+$", "x-proto-label": "LABEL_OPTIONAL",
+        }));
+        // patterns are passed through even if the regex crate can't parse them, like this lookahead
+        assert_eq!(properties["handle"]["pattern"], json!("^(?!admin)[a-z]+$"));
+        assert_eq!(properties["email"]["format"], json!("email"));
+        assert_eq!(properties["id"]["format"], json!("uuid"));
+    }
+
+    #[test]
+    fn validate_rules_constrain_numbers() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            import "validate/validate.proto";
+            message Reading {
+                double ratio = 1 [(validate.rules).double = { gt: 0, lte: 1 }];
+                int64 count = 2 [(validate.rules).int64 = { gte: 10, lt: 20 }];
+                int64 version = 3 [(validate.rules).int64 = { const: 3, gte: 0 }];
+            }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto_with_imports(source, &[("validate/validate.proto", PGV_VALIDATE_PROTO)], &options).unwrap();
+
+        let properties = &doc["components"]["schemas"]["Reading"]["properties"];
+        assert_eq!(properties["ratio"], json!({
+            "type": "number", "format": "double", "minimum": 0.0, "exclusiveMinimum": true, "maximum": 1.0,
+            "x-proto-label": "LABEL_OPTIONAL",
+        }));
+        assert_eq!(properties["count"], json!({
+            "type": "integer", "format": "int64", "minimum": 10, "maximum": 20, "exclusiveMaximum": true,
+            "x-proto-label": "LABEL_OPTIONAL",
+        }));
+        // a constant wins over the range
+        assert_eq!(properties["version"], json!({
+            "type": "integer", "format": "int64", "enum": [3], "x-proto-label": "LABEL_OPTIONAL",
+        }));
+    }
+
+    #[test]
+    fn proto3_optional_fields_are_nullable() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message Page {
+                optional int32 limit = 1;
+                int32 offset = 2;
+                optional string cursor = 3;
+            }
+        "#;
+        let options = GeneratorOptions { allow_empty_paths: true, ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        let page = &doc["components"]["schemas"]["Page"];
+        assert_eq!(page["properties"]["limit"], json!({
+            "type": "integer", "format": "int32", "nullable": true, "x-proto-label": "LABEL_OPTIONAL",
+        }));
+        assert_eq!(page["properties"]["cursor"]["nullable"], json!(true));
+        assert!(page["properties"]["offset"].get("nullable").is_none());
+        assert_eq!(page["required"], json!(["offset"]));
+    }
+
+    #[test]
+    fn path_prefix_is_normalized_and_adds_its_parameters() {
+        let source = r#"
+            syntax = "proto3";
+            package test;
+            message User { string id = 1; }
+            service Users {
+                // GET /users
+                rpc ListUsers(User) returns (User);
+                // GET /users/{id:string}
+                rpc GetUser(User) returns (User);
+            }
+        "#;
+        let options = GeneratorOptions { path_prefix: Some("api//tenants/{tenant:int}/".to_string()), ..GeneratorOptions::default() };
+        let doc = generate_proto(source, &options).unwrap();
+
+        let paths = doc["paths"].as_object().unwrap();
+        assert_eq!(paths.keys().collect_vec(), ["/api/tenants/{tenant}/users", "/api/tenants/{tenant}/users/{id}"]);
+        for path_item in paths.values() {
+            let tenant = &path_item["parameters"][0];
+            assert_eq!(tenant["name"], json!("tenant"));
+            assert_eq!(tenant["in"], json!("path"));
+            assert_eq!(tenant["schema"]["type"], json!("integer"));
         }
+        assert_eq!(paths["/api/tenants/{tenant}/users/{id}"]["parameters"][1]["name"], json!("id"));
+        assert_eq!(split_path_prefix("/"), (String::new(), Vec::new()));
     }
 }
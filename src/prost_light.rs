@@ -1,49 +1,231 @@
-use std::{path::Path, process::Command};
+use std::{collections::{HashMap, HashSet}, ffi::OsString, path::{Path, PathBuf}, process::Command, time::SystemTime};
 
-use prost_types::FileDescriptorSet;
+use prost_types::{DescriptorProto, FileDescriptorProto};
 use prost::Message;
 
+/// A FileDescriptorSet whose files are kept serialized, so merging sets keeps the extensions of their options.
+#[derive(Clone, PartialEq, Message)]
+struct SerializedFileDescriptorSet {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    file: Vec<Vec<u8>>,
+}
+
 /// A trait for getting the FieDescriptorSet from a `prost_build::Config`
 pub trait GetProtoFileDescriptor {
-    /// Invokes protoctl to get the FileDescriptorSet
-    fn get_descriptor(&mut self, protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Result<prost_types::FileDescriptorSet, Box<dyn std::error::Error>>;
+    /// Invokes protoctl to get the serialized FileDescriptorSet, which still contains the extensions of options
+    fn get_raw_descriptor(&mut self, protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+}
+
+/// The contents of a descriptor cache, the descriptor set along with the key of the protoc invocation producing it.
+#[derive(Clone, PartialEq, Message)]
+struct DescriptorCache {
+    #[prost(string, repeated, tag = "1")]
+    key: Vec<String>,
+    #[prost(bytes = "vec", tag = "2")]
+    descriptor: Vec<u8>,
+}
+
+/// The most files and directories looked at to decide whether a descriptor cache is outdated, since an include
+/// directory like `.` can contain a whole repository.
+const MAX_CACHE_SCAN_ENTRIES: usize = 10_000;
+
+/// Returns the arguments passed to protoc to compile the proto files, apart from the output.
+fn protoc_arguments(protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["--include_imports".into(), "--include_source_info".into()];
+    for include in includes {
+        args.push("-I".into());
+        args.push(include.as_ref().into());
+    }
+    args.push("-I".into());
+    args.push(prost_build::protoc_include().into());
+    args.extend(protos.iter().map(|proto| proto.as_ref().into()));
+
+    args
+}
+
+/// Returns the key of a descriptor cache, which changes with the protoc binary and its arguments.
+///
+/// Relative paths are resolved by protoc, so the working directory is part of the key as well.
+fn cache_key(protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Vec<String> {
+    let working_dir = std::env::current_dir().unwrap_or_default();
+    let mut key = vec![prost_build::protoc().to_string_lossy().into_owned(), working_dir.to_string_lossy().into_owned()];
+    key.extend(protoc_arguments(protos, includes).iter().map(|arg| arg.to_string_lossy().into_owned()));
+
+    key
 }
 
 impl GetProtoFileDescriptor for prost_build::Config {
-    /// Invokes protoctl to get the FileDescriptorSet
-    fn get_descriptor(&mut self, protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Result<prost_types::FileDescriptorSet, Box<dyn std::error::Error>> {
+    /// Invokes protoctl to get the serialized FileDescriptorSet
+    fn get_raw_descriptor(&mut self, protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let tmp = tempfile::Builder::new().prefix("prost-light-build").tempdir()?;
         let descriptor_path = tmp.path().join("prost-light-descriptor-set");
 
         let mut cmd = Command::new(prost_build::protoc());
-        cmd.arg("--include_imports")
-            .arg("--include_source_info")
-            .arg("-o")
-            .arg(&descriptor_path);
-        
-        for include in includes {
-            cmd.arg("-I").arg(include.as_ref());
-        }
-
-        cmd.arg("-I").arg(prost_build::protoc_include());
-
-        for proto in protos {
-            cmd.arg(proto.as_ref());
-        }
+        cmd.arg("-o").arg(&descriptor_path).args(protoc_arguments(protos, includes));
 
         let output = cmd.output().map_err(|error| {
             std::io::Error::new(error.kind(), format!("failed to invoke protoc (hint: https://docs.rs/prost-build/#sourcing-protoc): {}", error),)
         })?;
 
         if !output.status.success() {
-            return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, format!("protoc failed: {}", String::from_utf8_lossy(&output.stderr)))));
+            return Err(Box::new(std::io::Error::other(format!("protoc failed: {}", String::from_utf8_lossy(&output.stderr)))));
         }
 
         let buf = std::fs::read(descriptor_path)?;
-        let file_descriptor_set = FileDescriptorSet::decode(&*buf).map_err(|error| {
-            std::io::Error::new(std::io::ErrorKind::Other, format!("failed to decode FileDescriptorSet: {}", error),)
+
+        Ok(buf)
+    }
+}
+
+/// Returns the latest modification time of a proto file, or of the proto files below a directory.
+///
+/// Only proto files are considered, so other files written next to them (like the document) don't matter.
+/// Directories without proto files have no modification time. Symlinks are followed, but every directory is only
+/// looked at once, which ends symlink loops, and at most `MAX_CACHE_SCAN_ENTRIES` entries are looked at overall.
+fn latest_proto_modification(path: &Path, is_input: bool, visited_dirs: &mut HashSet<PathBuf>, scanned: &mut usize) -> std::io::Result<Option<SystemTime>> {
+    *scanned += 1;
+    if *scanned > MAX_CACHE_SCAN_ENTRIES {
+        return Err(std::io::Error::other(format!("more than {} files below the include directories", MAX_CACHE_SCAN_ENTRIES)));
+    }
+    let metadata = std::fs::metadata(path)?;
+    if metadata.is_dir() {
+        if !visited_dirs.insert(path.canonicalize()?) {
+            return Ok(None);
+        }
+        let mut latest = None;
+        for entry in std::fs::read_dir(path)? {
+            latest = latest.max(latest_proto_modification(&entry?.path(), false, visited_dirs, scanned)?);
+        }
+        Ok(latest)
+    } else if is_input || path.extension().is_some_and(|extension| extension == "proto") {
+        Ok(Some(metadata.modified()?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads the descriptor set cached at `cache`, unless it was written for other proto files, include directories or
+/// another protoc, or a proto file or a proto file in the include directories was modified after it was written.
+///
+/// Returns `None` if the cache is missing, outdated or any of the inputs can't be read, so protoc is run instead.
+pub fn read_cached_descriptor(cache: &Path, protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>]) -> Option<Vec<u8>> {
+    let cached = std::fs::metadata(cache).and_then(|metadata| metadata.modified()).ok()?;
+    let contents = DescriptorCache::decode(&std::fs::read(cache).ok()?[..]).ok()?;
+    if contents.key != cache_key(protos, includes) {
+        return None;
+    }
+
+    let (mut visited_dirs, mut scanned) = (HashSet::new(), 0);
+    let inputs = protos.iter().map(|proto| (proto.as_ref(), true));
+    let includes = includes.iter().map(|include| (include.as_ref(), false));
+    for (path, is_input) in inputs.chain(includes) {
+        match latest_proto_modification(path, is_input, &mut visited_dirs, &mut scanned) {
+            // is_none_or would need Rust 1.82
+            #[allow(clippy::unnecessary_map_or)]
+            Ok(modified) if modified.map_or(true, |modified| modified <= cached) => {}
+            Ok(_) => return None,
+            Err(err) => {
+                eprintln!("warning: not using the descriptor cache {}: {}", cache.display(), err);
+                return None;
+            }
+        }
+    }
+
+    Some(contents.descriptor)
+}
+
+/// Writes a descriptor set to the cache at `cache`, along with the key of the protoc invocation producing it.
+pub fn write_cached_descriptor(cache: &Path, protos: &[impl AsRef<Path>], includes: &[impl AsRef<Path>], descriptor: &[u8]) -> std::io::Result<()> {
+    let contents = DescriptorCache { key: cache_key(protos, includes), descriptor: descriptor.to_vec() };
+
+    std::fs::write(cache, contents.encode_to_vec())
+}
+
+/// Returns the fully qualified names of the messages, enums and services defined in a file, like `.helloworld.HelloMessage`.
+fn defined_types(file: &FileDescriptorProto) -> Vec<String> {
+    fn collect(messages: &[DescriptorProto], prefix: &str, names: &mut Vec<String>) {
+        for message in messages {
+            let name = format!("{}.{}", prefix, message.name());
+            names.extend(message.enum_type.iter().map(|e| format!("{}.{}", name, e.name())));
+            collect(&message.nested_type, &name, names);
+            names.push(name);
+        }
+    }
+
+    let prefix = if file.package().is_empty() { String::new() } else { format!(".{}", file.package()) };
+    let mut names = Vec::new();
+    collect(&file.message_type, &prefix, &mut names);
+    names.extend(file.enum_type.iter().map(|e| format!("{}.{}", prefix, e.name())));
+    names.extend(file.service.iter().map(|s| format!("{}.{}", prefix, s.name())));
+
+    names
+}
+
+/// Reads serialized FileDescriptorSets (like the ones written by `protoc -o`) and merges them into one serialized set.
+///
+/// Files contained in multiple sets (like common imports) are only kept once, the first occurrence wins.
+/// A type defined by more than one file is an error, because references to it would be ambiguous.
+pub fn read_descriptor_sets(paths: &[impl AsRef<Path>]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut merged = SerializedFileDescriptorSet::default();
+    let mut file_names = HashSet::new();
+    let mut type_files: HashMap<String, String> = HashMap::new();
+
+    for path in paths {
+        let path = path.as_ref();
+        let buf = std::fs::read(path).map_err(|error| {
+            std::io::Error::new(error.kind(), format!("failed to read descriptor set {}: {}", path.display(), error))
+        })?;
+        let set = SerializedFileDescriptorSet::decode(&*buf).map_err(|error| {
+            std::io::Error::other(format!("failed to decode descriptor set {}: {}", path.display(), error))
         })?;
-        
-        Ok(file_descriptor_set)
+
+        for raw_file in set.file {
+            let file = FileDescriptorProto::decode(&*raw_file).map_err(|error| {
+                std::io::Error::other(format!("failed to decode a file of descriptor set {}: {}", path.display(), error))
+            })?;
+            if !file_names.insert(file.name().to_string()) {
+                continue;
+            }
+            for type_name in defined_types(&file) {
+                if let Some(other) = type_files.insert(type_name.clone(), file.name().to_string()) {
+                    return Err(Box::new(std::io::Error::other(format!("{} is defined by both {} and {}", type_name, other, file.name()))));
+                }
+            }
+            merged.file.push(raw_file);
+        }
+    }
+
+    Ok(merged.encode_to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_is_only_used_for_the_same_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("first.proto"), dir.path().join("second.proto"));
+        std::fs::write(&first, "syntax = \"proto3\";").unwrap();
+        std::fs::write(&second, "syntax = \"proto3\";").unwrap();
+        let cache = dir.path().join("descriptor.cache");
+        write_cached_descriptor(&cache, &[&first], &[dir.path()], b"descriptor").unwrap();
+
+        assert_eq!(read_cached_descriptor(&cache, &[&first], &[dir.path()]).as_deref(), Some(&b"descriptor"[..]));
+        assert_eq!(read_cached_descriptor(&cache, &[&second], &[dir.path()]), None);
+        assert_eq!(read_cached_descriptor(&cache, &[&first], &[] as &[PathBuf]), None);
     }
-}
\ No newline at end of file
+
+    #[cfg(unix)]
+    #[test]
+    fn cache_survives_symlink_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let proto = dir.path().join("test.proto");
+        std::fs::write(&proto, "syntax = \"proto3\";").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("loop")).unwrap();
+        let cache = dir.path().join("descriptor.cache");
+        write_cached_descriptor(&cache, &[&proto], &[dir.path()], b"descriptor").unwrap();
+
+        assert_eq!(read_cached_descriptor(&cache, &[&proto], &[dir.path()]).as_deref(), Some(&b"descriptor"[..]));
+    }
+}